@@ -109,6 +109,9 @@ $(document).ready(function() {
                 broker_to_url(cluster_id, $(row).children()[0]);
                 bytes_to_human($(row).children()[2], "/s");
                 big_num_to_human($(row).children()[3], "msg/s");
+                if (data[5] == "Controller") {
+                    $(row).css("font-weight", "bold");
+                }
             }
         });
     });
@@ -168,6 +171,150 @@ $(document).ready(function() {
             }
         });
     });
+    $('#datatable-all-brokers-ajax').each(function(index) {
+        $(this).DataTable({
+            "search": { "regex": true},
+            "ajax": $(this).attr("data-url"),
+            "lengthMenu": [ [10, 50, 200, -1], [10, 50, 200, "All"] ],
+            "language": { "search": "Regex search:" },
+            "columnDefs": [
+                { "className": "dt-body-right", "targets": [ 4, 5, 6, 7, 8 ] }
+            ],
+            "processing": true,
+            "deferRender": true,
+            "stateSave": true,
+            "createdRow": function(row, data, index) {
+                var row = $(row).children();
+                broker_to_url(row[0].innerHTML, row[1]);
+                cluster_to_url(row[0]);
+                bytes_to_human(row[7], "/s");
+                big_num_to_human(row[8], "msg/s");
+            }
+        });
+    });
+    $('#datatable-broker-partitions-ajax').each(function(index) {
+        $(this).DataTable({
+            "search": { "regex": true},
+            "ajax": $(this).attr("data-url"),
+            "lengthMenu": [ [10, 50, 200, -1], [10, 50, 200, "All"] ],
+            "language": { "search": "Regex search:" },
+            "columnDefs": [ ],
+            "processing": true,
+            "deferRender": true,
+            stateSave: true,
+            "createdRow": function(row, data, index) {
+                var cluster_id = $(this).attr("data-param");
+                topic_to_url(cluster_id, $(row).children()[0]);
+                error_to_graphic($(row).children()[3]);
+            }
+        });
+    });
+    $('#datatable-broker-topics-ajax').each(function(index) {
+        $(this).DataTable({
+            "search": { "regex": true},
+            "ajax": $(this).attr("data-url"),
+            "lengthMenu": [ [10, 50, 200, -1], [10, 50, 200, "All"] ],
+            "language": { "search": "Regex search:" },
+            "columnDefs": [
+                { "className": "dt-body-right", "targets": [ 1, 2 ] }
+            ],
+            "processing": true,
+            "deferRender": true,
+            stateSave: true,
+            "createdRow": function(row, data, index) {
+                var cluster_id = $(this).attr("data-param");
+                topic_to_url(cluster_id, $(row).children()[0]);
+                bytes_to_human($(row).children()[1], "/s");
+                big_num_to_human($(row).children()[2], "msg/s");
+            }
+        });
+    });
+    $('#datatable-topic-config-ajax, #datatable-broker-config-ajax').each(function(index) {
+        $(this).DataTable({
+            "search": { "regex": true},
+            "ajax": $(this).attr("data-url"),
+            "lengthMenu": [ [10, 50, 200, -1], [10, 50, 200, "All"] ],
+            "language": { "search": "Regex search:" },
+            "order": [ [2, "desc"], [0, "asc"] ],
+            "columnDefs": [ ],
+            "processing": true,
+            "deferRender": true,
+            stateSave: true,
+            "createdRow": function(row, data, index) {
+                if (data[2] == "override") {
+                    $(row).css("font-weight", "bold");
+                }
+            }
+        });
+    });
+    $('#datatable-distribution-brokers-ajax').each(function(index) {
+        $(this).DataTable({
+            "search": { "regex": true},
+            "ajax": $(this).attr("data-url"),
+            "lengthMenu": [ [10, 50, 200, -1], [10, 50, 200, "All"] ],
+            "language": { "search": "Regex search:" },
+            "columnDefs": [
+                { "className": "dt-body-right", "targets": [ 1, 2, 3 ] }
+            ],
+            "processing": true,
+            "deferRender": true,
+            stateSave: true,
+            "createdRow": function(row, data, index) {
+                var cluster_id = $(this).attr("data-param");
+                broker_to_url(cluster_id, $(row).children()[0]);
+            }
+        });
+    });
+    $('#datatable-distribution-topics-ajax').each(function(index) {
+        $(this).DataTable({
+            "search": { "regex": true},
+            "ajax": $(this).attr("data-url"),
+            "lengthMenu": [ [10, 50, 200, -1], [10, 50, 200, "All"] ],
+            "language": { "search": "Regex search:" },
+            "order": [ [3, "desc"] ],
+            "columnDefs": [
+                { "className": "dt-body-right", "targets": [ 1, 2, 3 ] }
+            ],
+            "processing": true,
+            "deferRender": true,
+            stateSave: true,
+            "createdRow": function(row, data, index) {
+                var cluster_id = $(this).attr("data-param");
+                topic_to_url(cluster_id, $(row).children()[0]);
+            }
+        });
+    });
+    $('#datatable-rack-violations-ajax').each(function(index) {
+        $(this).DataTable({
+            "search": { "regex": true},
+            "ajax": $(this).attr("data-url"),
+            "lengthMenu": [ [10, 50, 200, -1], [10, 50, 200, "All"] ],
+            "language": { "search": "Regex search:" },
+            "columnDefs": [
+                { "className": "dt-body-right", "targets": [ 1 ] }
+            ],
+            "processing": true,
+            "deferRender": true,
+            stateSave: true,
+            "createdRow": function(row, data, index) {
+                var cluster_id = $(this).attr("data-param");
+                topic_to_url(cluster_id, $(row).children()[0]);
+            }
+        });
+    });
+    $('#datatable-events-ajax').each(function(index) {
+        $(this).DataTable({
+            "search": { "regex": true},
+            "ajax": $(this).attr("data-url"),
+            "lengthMenu": [ [10, 50, 200, -1], [10, 50, 200, "All"] ],
+            "language": { "search": "Regex search:" },
+            "order": [ [0, "desc"] ],
+            "columnDefs": [ ],
+            "processing": true,
+            "deferRender": true,
+            stateSave: true
+        });
+    });
     $('#datatable-group-members-ajax').each(function(index) {
         $(this).DataTable({
             "search": { "regex": true},
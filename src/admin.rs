@@ -0,0 +1,126 @@
+use error::*;
+use kafka_api::{self, ClusterMetadata, ConfigResource};
+use metadata::BrokerId;
+
+use std::time::Duration;
+
+
+/// Sends admin requests to a cluster. librdkafka doesn't support the admin API, so topic creation
+/// and configuration description are implemented on top of the Kafka protocol.
+pub struct KafkaAdmin {
+    bootstrap_servers: Vec<(String, i32)>,
+}
+
+impl KafkaAdmin {
+    /// Sends the request to each bootstrap server in turn, until one of them succeeds.
+    fn with_any_server<T, F>(&self, f: F) -> Result<T>
+            where F: Fn(&str, i32) -> Result<T> {
+        let mut last_error = None;
+        for &(ref host, port) in &self.bootstrap_servers {
+            match f(host, port) {
+                Ok(result) => return Ok(result),
+                Err(e) => {
+                    debug!("Admin request to {}:{} failed: {}", host, port, e);
+                    last_error = Some(e);
+                },
+            }
+        }
+        Err(last_error.unwrap_or_else(|| "No bootstrap server available".into()))
+    }
+
+    pub fn cluster_metadata(&self, timeout: Duration) -> Result<ClusterMetadata> {
+        self.with_any_server(|host, port| kafka_api::fetch_cluster_metadata(host, port, timeout))
+    }
+}
+
+/// A configuration entry of a topic or a broker.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ConfigValue {
+    pub name: String,
+    pub value: Option<String>,
+    /// False if the value was explicitly set for the topic or the broker.
+    pub is_default: bool,
+}
+
+fn parse_server(server: &str) -> Result<(String, i32)> {
+    match server.rfind(':') {
+        Some(index) => {
+            let port = server[index + 1..].parse::<i32>()
+                .chain_err(|| format!("Invalid port in {}", server))?;
+            Ok((server[..index].to_owned(), port))
+        },
+        None => Ok((server.to_owned(), 9092)),
+    }
+}
+
+pub fn create_admin(brokers: &str) -> Result<KafkaAdmin> {
+    let bootstrap_servers = brokers.split(',')
+        .map(|server| server.trim())
+        .filter(|server| !server.is_empty())
+        .map(parse_server)
+        .collect::<Result<Vec<_>>>()
+        .chain_err(|| "Admin client creation failed")?;
+    if bootstrap_servers.is_empty() {
+        bail!("Admin client creation failed: no bootstrap server");
+    }
+    Ok(KafkaAdmin { bootstrap_servers: bootstrap_servers })
+}
+
+/// Returns the configuration of the topic, including the default values.
+/// Whether the cluster can describe configurations. Fails if the API versions of the cluster
+/// can't be fetched.
+pub fn describe_configs_supported(admin: &KafkaAdmin, timeout: Duration) -> Result<bool> {
+    admin.with_any_server(|host, port| kafka_api::fetch_api_versions(host, port, timeout))
+        .map(|api_versions| kafka_api::supports_describe_configs(&api_versions))
+}
+
+pub fn describe_topic_config(admin: &KafkaAdmin, topic_name: &str, timeout: Duration) -> Result<Vec<ConfigValue>> {
+    admin.with_any_server(|host, port| {
+            kafka_api::describe_configs(host, port, &[ConfigResource::Topic(topic_name)], timeout)
+        })
+        .and_then(|mut results| results.remove(0))
+        .chain_err(|| format!("Failed to describe configuration of topic {}", topic_name))
+}
+
+/// Returns the configuration of every topic, including the default values. Topics whose
+/// configuration couldn't be described are returned with an error.
+pub fn describe_topic_configs(admin: &KafkaAdmin, topic_names: &[String], timeout: Duration)
+        -> Result<Vec<(String, Result<Vec<ConfigValue>>)>> {
+    let resources = topic_names.iter()
+        .map(|name| ConfigResource::Topic(name))
+        .collect::<Vec<_>>();
+    let results = admin.with_any_server(|host, port| kafka_api::describe_configs(host, port, &resources, timeout))
+        .chain_err(|| "Failed to describe topic configurations")?;
+    Ok(topic_names.iter().cloned().zip(results).collect())
+}
+
+/// Returns the configuration of every broker, including the default values. Each broker is asked
+/// for its own configuration; brokers whose configuration couldn't be described are returned
+/// with an error.
+pub fn describe_broker_configs(admin: &KafkaAdmin, broker_ids: &[BrokerId], timeout: Duration)
+        -> Result<Vec<(BrokerId, Result<Vec<ConfigValue>>)>> {
+    let metadata = admin.cluster_metadata(timeout)
+        .chain_err(|| "Failed to describe broker configurations")?;
+    let results = broker_ids.iter()
+        .map(|&broker_id| {
+            let result = match metadata.hosts.get(&broker_id) {
+                Some(&(ref host, port)) =>
+                    kafka_api::describe_configs(host, port, &[ConfigResource::Broker(broker_id)], timeout)
+                        .and_then(|mut results| results.remove(0)),
+                None => Err(format!("Broker {} is not in the cluster metadata", broker_id).into()),
+            };
+            (broker_id, result)
+        })
+        .collect();
+    Ok(results)
+}
+
+/// Creates a topic with the specified topic configuration.
+pub fn create_topic(admin: &KafkaAdmin, topic_name: &str, partitions: i32, replication_factor: i32,
+                    config: &[(&str, &str)], timeout: Duration) -> Result<()> {
+    let metadata = admin.cluster_metadata(timeout)
+        .chain_err(|| format!("Failed to create topic {}", topic_name))?;
+    let &(ref host, port) = metadata.hosts.get(&metadata.controller_id)
+        .ok_or_else(|| Error::from(format!("Controller {} is not in the cluster metadata", metadata.controller_id)))?;
+    kafka_api::create_topic(host, port, topic_name, partitions, replication_factor as i16, config, timeout)
+}
@@ -0,0 +1,98 @@
+use chrono::{DateTime, UTC};
+
+use std::collections::VecDeque;
+
+
+/// Length of the window used to compute the rates, in seconds.
+const RATE_WINDOW: i64 = 60;
+
+/// Counts the events of the last minute, to compute their rate.
+pub struct RateMeter {
+    // Number of events for each second, as (second, count)
+    buckets: VecDeque<(i64, usize)>,
+}
+
+impl RateMeter {
+    pub fn new() -> RateMeter {
+        RateMeter { buckets: VecDeque::new() }
+    }
+
+    /// Records an event that happened at the specified second.
+    pub fn record(&mut self, now: i64) {
+        let new_bucket = match self.buckets.back_mut() {
+            Some(&mut (second, ref mut count)) if second == now => {
+                *count += 1;
+                false
+            },
+            _ => true,
+        };
+        if new_bucket {
+            self.buckets.push_back((now, 1));
+        }
+        self.discard_before(now - RATE_WINDOW);
+    }
+
+    /// Events per second, averaged over the last minute.
+    pub fn rate(&mut self, now: i64) -> f64 {
+        self.discard_before(now - RATE_WINDOW);
+        let count = self.buckets.iter().map(|&(_, count)| count).sum::<usize>();
+        count as f64 / RATE_WINDOW as f64
+    }
+
+    fn discard_before(&mut self, second: i64) {
+        while self.buckets.front().map(|&(s, _)| s <= second).unwrap_or(false) {
+            self.buckets.pop_front();
+        }
+    }
+}
+
+/// What happened to a map since this instance started.
+pub struct MapActivity {
+    /// Writes to the backend done by this instance.
+    pub writes: RateMeter,
+    /// Update time of the most recent change, local or replicated.
+    pub last_update: Option<DateTime<UTC>>,
+    /// Updates received from the backend, either while loading the state or from other
+    /// instances.
+    pub replayed: usize,
+}
+
+impl MapActivity {
+    pub fn new() -> MapActivity {
+        MapActivity {
+            writes: RateMeter::new(),
+            last_update: None,
+            replayed: 0,
+        }
+    }
+
+    pub fn record_write(&mut self) {
+        self.writes.record(UTC::now().timestamp());
+    }
+
+    pub fn record_update(&mut self, updated: Option<DateTime<UTC>>) {
+        let updated = updated.unwrap_or_else(UTC::now);
+        if self.last_update.map(|last| updated > last).unwrap_or(true) {
+            self.last_update = Some(updated);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_covers_the_last_minute() {
+        let mut meter = RateMeter::new();
+        for _ in 0..30 {
+            meter.record(1000);
+        }
+        for _ in 0..30 {
+            meter.record(1030);
+        }
+        assert_eq!(meter.rate(1030), 1.0);
+        assert_eq!(meter.rate(1070), 0.5);
+        assert_eq!(meter.rate(1100), 0.0);
+    }
+}
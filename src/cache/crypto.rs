@@ -0,0 +1,217 @@
+use ring::aead::{self, CHACHA20_POLY1305, OpeningKey, SealingKey};
+use ring::digest::{self, SHA256};
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::str;
+
+use error::*;
+
+
+/// First byte of every encrypted record, to tell it apart from unencrypted data.
+const ENCRYPTED_MARKER: u8 = 0xE1;
+const KEY_ID_LEN: usize = 4;
+const NONCE_LEN: usize = 12;
+
+/// A key loaded from the key file.
+struct CipherKey {
+    id: [u8; KEY_ID_LEN],
+    sealing: SealingKey,
+    opening: OpeningKey,
+    nonce_key: hmac::SigningKey,
+}
+
+impl CipherKey {
+    fn new(key_bytes: &[u8]) -> Result<CipherKey> {
+        if key_bytes.len() != CHACHA20_POLY1305.key_len() {
+            bail!("Encryption keys must be {} bytes long, found {}", CHACHA20_POLY1305.key_len(), key_bytes.len());
+        }
+        // Separate subkeys are derived for encryption and nonce generation
+        let master = hmac::SigningKey::new(&SHA256, key_bytes);
+        let encryption_key = hmac::sign(&master, b"kafka-view encryption");
+        let nonce_key = hmac::sign(&master, b"kafka-view nonce");
+        let mut id = [0u8; KEY_ID_LEN];
+        id.copy_from_slice(&digest::digest(&SHA256, key_bytes).as_ref()[..KEY_ID_LEN]);
+        Ok(CipherKey {
+            id: id,
+            sealing: SealingKey::new(&CHACHA20_POLY1305, encryption_key.as_ref())
+                .map_err(|_| Error::from("Invalid encryption key"))?,
+            opening: OpeningKey::new(&CHACHA20_POLY1305, encryption_key.as_ref())
+                .map_err(|_| Error::from("Invalid encryption key"))?,
+            nonce_key: hmac::SigningKey::new(&SHA256, nonce_key.as_ref()),
+        })
+    }
+}
+
+/// Loads the keys from a file containing one hex encoded 32 bytes key per line. Empty lines and
+/// lines starting with `#` are ignored.
+fn read_key_file(path: &Path) -> Result<Vec<CipherKey>> {
+    let file = File::open(path)
+        .chain_err(|| format!("Failed to open key file {}", path.display()))?;
+    let mut keys = Vec::new();
+    for (line_n, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.chain_err(|| format!("Failed to read key file {}", path.display()))?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let key = parse_hex(line)
+            .and_then(|bytes| CipherKey::new(&bytes))
+            .chain_err(|| format!("Invalid key at line {} of {}", line_n + 1, path.display()))?;
+        keys.push(key);
+    }
+    if keys.is_empty() {
+        bail!("No key found in {}", path.display());
+    }
+    Ok(keys)
+}
+
+/// Authenticated encryption of the records written to the replica topic, using
+/// ChaCha20-Poly1305. Every encrypted record starts with the id of the key used to encrypt it,
+/// so that records written with older keys can still be decrypted after a key rotation. Message
+/// keys are encrypted with a separate key that is never rotated, so that rotating the payload
+/// keys doesn't change the keys of the compacted topic.
+pub struct Cipher {
+    encryption_key: CipherKey,
+    decryption_keys: Vec<CipherKey>,
+    key_encryption_key: CipherKey,
+    random: SystemRandom,
+}
+
+impl Cipher {
+    /// Loads the payload keys and the key-encryption key. The first payload key is used for
+    /// encryption and decryption, the following ones only for decryption. The key-encryption key
+    /// file must contain exactly one key.
+    pub fn from_key_files(keys_path: &Path, key_encryption_key_path: &Path) -> Result<Cipher> {
+        let mut keys = read_key_file(keys_path)?;
+        let mut key_encryption_keys = read_key_file(key_encryption_key_path)?;
+        if key_encryption_keys.len() != 1 {
+            bail!("{} must contain a single key, found {}", key_encryption_key_path.display(),
+                  key_encryption_keys.len());
+        }
+        let encryption_key = keys.remove(0);
+        Ok(Cipher {
+            encryption_key: encryption_key,
+            decryption_keys: keys,
+            key_encryption_key: key_encryption_keys.remove(0),
+            random: SystemRandom::new(),
+        })
+    }
+
+    /// Encrypts the data using a random nonce.
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.random.fill(&mut nonce)
+            .map_err(|_| Error::from("Failed to generate nonce"))?;
+        self.seal(&self.encryption_key, &nonce, plaintext)
+    }
+
+    /// Encrypts the data with the key-encryption key, using a nonce derived from the data itself:
+    /// the same plaintext is always encrypted to the same ciphertext, even after a rotation of
+    /// the payload keys. Used for the message keys, which need to stay the same for the topic
+    /// compaction to work.
+    pub fn encrypt_deterministic(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce.copy_from_slice(&hmac::sign(&self.key_encryption_key.nonce_key, plaintext).as_ref()[..NONCE_LEN]);
+        self.seal(&self.key_encryption_key, &nonce, plaintext)
+    }
+
+    fn seal(&self, key: &CipherKey, nonce: &[u8; NONCE_LEN], plaintext: &[u8]) -> Result<Vec<u8>> {
+        let tag_len = CHACHA20_POLY1305.tag_len();
+        let header_len = 1 + KEY_ID_LEN + NONCE_LEN;
+        let mut record = Vec::with_capacity(header_len + plaintext.len() + tag_len);
+        record.push(ENCRYPTED_MARKER);
+        record.extend_from_slice(&key.id);
+        record.extend_from_slice(nonce);
+        record.extend_from_slice(plaintext);
+        record.extend(::std::iter::repeat(0u8).take(tag_len));
+        let sealed_len = {
+            let (header, in_out) = record.split_at_mut(header_len);
+            aead::seal_in_place(&key.sealing, nonce, header, in_out, tag_len)
+                .map_err(|_| Error::from("Encryption failed"))?
+        };
+        record.truncate(header_len + sealed_len);
+        Ok(record)
+    }
+
+    /// Decrypts a record encrypted with any of the keys, checking its authenticity.
+    pub fn decrypt(&self, record: &[u8]) -> Result<Vec<u8>> {
+        let header_len = 1 + KEY_ID_LEN + NONCE_LEN;
+        if record.len() < header_len + CHACHA20_POLY1305.tag_len() || record[0] != ENCRYPTED_MARKER {
+            bail!("The record is not encrypted");
+        }
+        let key_id = &record[1..1 + KEY_ID_LEN];
+        let key = match self.keys().find(|key| &key.id[..] == key_id) {
+            Some(key) => key,
+            None => bail!("The record was encrypted with an unknown key"),
+        };
+        let nonce = &record[1 + KEY_ID_LEN..header_len];
+        let mut ciphertext = record[header_len..].to_vec();
+        let plaintext_len = aead::open_in_place(&key.opening, nonce, &record[..header_len], 0, &mut ciphertext)
+            .map_err(|_| Error::from("Decryption failed, the record might have been tampered with"))?
+            .len();
+        ciphertext.truncate(plaintext_len);
+        Ok(ciphertext)
+    }
+
+    fn keys<'a>(&'a self) -> Box<Iterator<Item=&'a CipherKey> + 'a> {
+        Box::new(Some(&self.encryption_key).into_iter()
+            .chain(self.decryption_keys.iter())
+            .chain(Some(&self.key_encryption_key)))
+    }
+}
+
+fn parse_hex(hex: &str) -> Result<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        bail!("Odd number of hex digits");
+    }
+    hex.as_bytes().chunks(2)
+        .map(|pair| {
+            str::from_utf8(pair).ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| Error::from("Invalid hex digit"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cipher(keys: &[&str]) -> Cipher {
+        let mut keys = keys.iter().map(|k| CipherKey::new(&parse_hex(k).unwrap()).unwrap()).collect::<Vec<_>>();
+        Cipher {
+            encryption_key: keys.remove(0),
+            decryption_keys: keys,
+            key_encryption_key: CipherKey::new(&parse_hex(KEY_ENCRYPTION_KEY).unwrap()).unwrap(),
+            random: SystemRandom::new(),
+        }
+    }
+
+    const KEY_1: &'static str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+    const KEY_2: &'static str = "1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100";
+    const KEY_ENCRYPTION_KEY: &'static str = "202122232425262728292a2b2c2d2e2f303132333435363738393a3b3c3d3e3f";
+
+    #[test]
+    fn encryption_roundtrip_and_rotation() {
+        let old = cipher(&[KEY_1]);
+        let rotated = cipher(&[KEY_2, KEY_1]);
+
+        let encrypted = old.encrypt(b"payload").unwrap();
+        assert_eq!(rotated.decrypt(&encrypted).unwrap(), b"payload".to_vec());
+        assert!(old.decrypt(&rotated.encrypt(b"payload").unwrap()).is_err());
+
+        let key = rotated.encrypt_deterministic(b"key").unwrap();
+        assert_eq!(key, rotated.encrypt_deterministic(b"key").unwrap());
+        assert_eq!(key, old.encrypt_deterministic(b"key").unwrap());
+        assert_eq!(rotated.decrypt(&key).unwrap(), b"key".to_vec());
+
+        let mut tampered = encrypted.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        assert!(rotated.decrypt(&tampered).is_err());
+    }
+}
@@ -0,0 +1,146 @@
+use serde::de::Deserialize;
+use serde::ser::Serialize;
+use serde_json;
+
+use std::hash::Hash;
+use std::io::{BufRead, Write};
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use cache::{Cache, ClusterKey, EntryInfo, ReplicatedMap};
+use error::*;
+
+
+/// A line of the export file, with the decoded key and value of a cache entry.
+#[derive(Serialize)]
+struct ExportRecord<'a, K: 'a, V: 'a> {
+    map: &'a str,
+    key: &'a K,
+    value: &'a V,
+    info: &'a EntryInfo,
+}
+
+#[derive(Deserialize)]
+struct ImportRecord {
+    map: String,
+    key: serde_json::Value,
+    value: serde_json::Value,
+    info: EntryInfo,
+}
+
+impl<K, V> ReplicatedMap<K, V> where K: Eq + Hash + Clone + Serialize + Deserialize + ClusterKey,
+                                     V: Clone + PartialEq + Serialize + Deserialize {
+    /// Writes every entry of the map as a JSON line, returning the number of entries written.
+    fn export_json<W: Write>(&self, writer: &mut W) -> Result<usize> {
+        let mut count = 0;
+        for key in self.keys() {
+            // The entry might have been removed in the meantime
+            if let Some((value, info)) = self.get_with_info(&key) {
+                let record = ExportRecord { map: self.name(), key: &key, value: &value, info: &info };
+                serde_json::to_writer(writer, &record)
+                    .chain_err(|| "Failed to serialize record")?;
+                writer.write_all(b"\n").chain_err(|| "Failed to write record")?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    fn import_json(&self, record: ImportRecord) -> Result<()> {
+        let key = serde_json::from_value::<K>(record.key)
+            .chain_err(|| "Failed to parse key")?;
+        let value = serde_json::from_value::<V>(record.value)
+            .chain_err(|| "Failed to parse value")?;
+        self.insert_with_info(key, value, record.info)
+    }
+
+    /// Looks up a key written in the same JSON format used by the export, returning the decoded
+    /// value as JSON.
+    fn lookup_json(&self, key: &str) -> Result<Option<(serde_json::Value, EntryInfo)>> {
+        let key = serde_json::from_str::<K>(key)
+            .chain_err(|| format!("Invalid key for map {}", self.name()))?;
+        match self.get_with_info(&key) {
+            Some((value, info)) => {
+                let value = serde_json::to_value(&value)
+                    .chain_err(|| "Failed to serialize value")?;
+                Ok(Some((value, info)))
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+impl Cache {
+    /// Writes the content of all the maps as JSON lines.
+    pub fn export_json<W: Write>(&self, writer: &mut W) -> Result<usize> {
+        let mut count = 0;
+        count += self.metrics.export_json(writer)?;
+        count += self.offsets.export_json(writer)?;
+        count += self.brokers.export_json(writer)?;
+        count += self.topics.export_json(writer)?;
+        count += self.groups.export_json(writer)?;
+        count += self.topic_configs.export_json(writer)?;
+        count += self.broker_configs.export_json(writer)?;
+        count += self.events.export_json(writer)?;
+        Ok(count)
+    }
+
+    /// Writes the entries read from a JSON lines export to the cache, keeping their original
+    /// update time and source.
+    pub fn import_json<R: BufRead>(&self, reader: R) -> Result<usize> {
+        let mut count = 0;
+        for (line_n, line) in reader.lines().enumerate() {
+            let line = line.chain_err(|| "Failed to read line")?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record = serde_json::from_str::<ImportRecord>(&line)
+                .chain_err(|| format!("Failed to parse line {}", line_n + 1))?;
+            let result = match record.map.as_ref() {
+                "metrics" => self.metrics.import_json(record),
+                "offsets" => self.offsets.import_json(record),
+                "brokers" => self.brokers.import_json(record),
+                "topics" => self.topics.import_json(record),
+                "groups" => self.groups.import_json(record),
+                "topic_configs" => self.topic_configs.import_json(record),
+                "broker_configs" => self.broker_configs.import_json(record),
+                "events" => self.events.import_json(record),
+                name => bail!("Unknown cache name at line {}: {}", line_n + 1, name),
+            };
+            result.chain_err(|| format!("Failed to import line {}", line_n + 1))?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Returns the decoded value of a key of the named map. The key is in the JSON format used
+    /// by the export, for example `["cluster", "group", "topic"]` for the offsets.
+    pub fn lookup_json(&self, map_name: &str, key: &str) -> Result<Option<(serde_json::Value, EntryInfo)>> {
+        match map_name {
+            "metrics" => self.metrics.lookup_json(key),
+            "offsets" => self.offsets.lookup_json(key),
+            "brokers" => self.brokers.lookup_json(key),
+            "topics" => self.topics.lookup_json(key),
+            "groups" => self.groups.lookup_json(key),
+            "topic_configs" => self.topic_configs.lookup_json(key),
+            "broker_configs" => self.broker_configs.lookup_json(key),
+            "events" => self.events.lookup_json(key),
+            _ => bail!("Unknown cache name: {}", map_name),
+        }
+    }
+
+    /// Waits until all the writes to the backend are confirmed, or the timeout expires.
+    /// Returns the number of writes that failed.
+    pub fn wait_writes(&self, timeout: Duration) -> Result<usize> {
+        let counters = self.backend.write_counters();
+        let start = Instant::now();
+        while counters.in_flight.load(Ordering::Relaxed) > 0 {
+            if start.elapsed() > timeout {
+                bail!("{} writes not confirmed after {:?}", counters.in_flight.load(Ordering::Relaxed), timeout);
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        Ok(counters.failed.load(Ordering::Relaxed))
+    }
+}
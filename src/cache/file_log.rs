@@ -0,0 +1,210 @@
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use serde_cbor;
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+use cache::{LastUpdates, ReplicaBackend, ReplicaCacheUpdate, ReplicaPositions, UpdateReceiver, WrappedKey,
+            WriteCounters};
+use error::*;
+
+
+/// A single entry of the log. A `None` payload is a tombstone.
+#[derive(Serialize, Deserialize, Debug)]
+struct LogRecord {
+    key: WrappedKey,
+    payload: Option<Vec<u8>>,
+}
+
+/// Stores the cache state in an append-only file on the local disk. Each record is written as a
+/// big endian u32 length followed by the CBOR serialization of the record. The file is
+/// periodically rewritten keeping only the last record for every key.
+pub struct FileLogBackend {
+    instance_id: String,
+    path: PathBuf,
+    file: Arc<Mutex<File>>,
+    counters: WriteCounters,
+}
+
+impl FileLogBackend {
+    pub fn new(path: &str, compaction_interval: Duration, instance_id: &str) -> Result<FileLogBackend> {
+        let path = PathBuf::from(path);
+        let file = open_log(&path)?;
+        let file = Arc::new(Mutex::new(file));
+
+        let path_clone = path.clone();
+        let file_clone = file.clone();
+        thread::Builder::new()
+            .name("File log compaction".into())
+            .spawn(move || loop {
+                thread::sleep(compaction_interval);
+                if let Err(e) = compact_log(&path_clone, &file_clone) {
+                    format_error_chain!(e);
+                }
+            })
+            .chain_err(|| "Failed to start compaction thread")?;
+
+        Ok(FileLogBackend {
+            instance_id: instance_id.to_owned(),
+            path: path,
+            file: file,
+            counters: WriteCounters::default(),
+        })
+    }
+}
+
+impl ReplicaBackend for FileLogBackend {
+    fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    fn write_update(&self, key: &WrappedKey, payload: Option<&[u8]>) -> Result<()> {
+        let record = LogRecord {
+            key: key.clone(),
+            payload: payload.map(|p| p.to_vec()),
+        };
+        let mut file = self.file.lock()
+            .map_err(|_| ErrorKind::PoisonError("writing to file log".to_owned()))?;
+        self.counters.written.fetch_add(1, Ordering::Relaxed);
+        let result = write_record(&mut *file, &record)
+            .chain_err(|| format!("Failed to write to {}", self.path.display()));
+        // Writes are synchronous: they are delivered as soon as they are written
+        match result {
+            Ok(_) => self.counters.delivered.fetch_add(1, Ordering::Relaxed),
+            Err(_) => self.counters.failed.fetch_add(1, Ordering::Relaxed),
+        };
+        result
+    }
+
+    fn write_counters(&self) -> &WriteCounters {
+        &self.counters
+    }
+
+    fn load_state(&self, receiver: &UpdateReceiver, _from: Option<&ReplicaPositions>) -> Result<()> {
+        info!("Started creating state from {}", self.path.display());
+        let state = {
+            let _file = self.file.lock()
+                .map_err(|_| ErrorKind::PoisonError("reading file log".to_owned()))?;
+            last_record_per_key(&self.path)?
+        };
+        for (w_key, payload) in state.into_ordered() {
+            let update = match payload {
+                Some(ref payload) => ReplicaCacheUpdate::Set {
+                    key: &w_key,
+                    payload: payload
+                },
+                None => ReplicaCacheUpdate::Delete {
+                    key: &w_key
+                },
+            };
+            if let Err(e) = receiver.receive_update(w_key.cache_name(), update) {
+                format_error_chain!(e);
+            }
+        }
+        info!("State creation terminated");
+        Ok(())
+    }
+}
+
+fn open_log(path: &Path) -> Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+        .chain_err(|| format!("Failed to open {}", path.display()))
+}
+
+fn write_record<W: Write>(writer: &mut W, record: &LogRecord) -> Result<()> {
+    let bytes = serde_cbor::to_vec(record)
+        .chain_err(|| "Failed to serialize record")?;
+    writer.write_u32::<BigEndian>(bytes.len() as u32)
+        .and_then(|_| writer.write_all(&bytes))
+        .chain_err(|| "Failed to write record")
+}
+
+/// Reads the next record, returning `None` at the end of the file. A record truncated by a crash
+/// is treated as the end of the file.
+fn read_record<R: Read>(reader: &mut R) -> Result<Option<LogRecord>> {
+    let len = match reader.read_u32::<BigEndian>() {
+        Ok(len) => len as usize,
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e).chain_err(|| "Failed to read record length"),
+    };
+    let mut bytes = vec![0u8; len];
+    match reader.read_exact(&mut bytes) {
+        Ok(()) => {},
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+            warn!("Truncated record found at the end of the file log");
+            return Ok(None);
+        },
+        Err(e) => return Err(e).chain_err(|| "Failed to read record"),
+    };
+    let record = serde_cbor::from_slice::<LogRecord>(&bytes)
+        .chain_err(|| "Failed to parse record")?;
+    Ok(Some(record))
+}
+
+fn last_record_per_key(path: &Path) -> Result<LastUpdates> {
+    let file = File::open(path)
+        .chain_err(|| format!("Failed to open {}", path.display()))?;
+    let mut reader = BufReader::new(file);
+    let mut state = LastUpdates::default();
+    while let Some(record) = read_record(&mut reader)? {
+        state.insert(record.key, record.payload);
+    }
+    Ok(state)
+}
+
+/// Rewrites the log keeping only the last record for each key, and dropping tombstones. The file
+/// lock is held for the whole compaction, so writes are blocked until it completes.
+fn compact_log(path: &Path, file: &Mutex<File>) -> Result<()> {
+    let mut file = file.lock()
+        .map_err(|_| ErrorKind::PoisonError("compacting file log".to_owned()))?;
+
+    let state = last_record_per_key(path)?;
+    let tmp_path = path.with_extension("compacting");
+    {
+        let tmp_file = File::create(&tmp_path)
+            .chain_err(|| format!("Failed to create {}", tmp_path.display()))?;
+        let mut writer = BufWriter::new(tmp_file);
+        for (key, payload) in state.into_ordered() {
+            if payload.is_some() {
+                write_record(&mut writer, &LogRecord { key: key, payload: payload })?;
+            }
+        }
+        let tmp_file = writer.into_inner()
+            .map_err(|e| Error::from(e.to_string()))?;
+        tmp_file.sync_all().chain_err(|| "Failed to sync compacted file log")?;
+    }
+    fs::rename(&tmp_path, path)
+        .chain_err(|| format!("Failed to replace {}", path.display()))?;
+    *file = open_log(path)?;
+    debug!("File log {} compacted", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn records_roundtrip_and_truncation() {
+        let mut buffer = Vec::new();
+        let key = WrappedKey::new("topics".to_owned(), &"key".to_owned()).unwrap();
+        write_record(&mut buffer, &LogRecord { key: key.clone(), payload: Some(vec![1, 2, 3]) }).unwrap();
+        write_record(&mut buffer, &LogRecord { key: key.clone(), payload: None }).unwrap();
+        let full_len = buffer.len();
+        buffer.extend_from_slice(&[0, 0, 0, 10, 1]);  // Truncated record
+
+        let mut reader = Cursor::new(buffer);
+        let first = read_record(&mut reader).unwrap().unwrap();
+        assert_eq!(first.key, key);
+        assert_eq!(first.payload, Some(vec![1, 2, 3]));
+        assert!(read_record(&mut reader).unwrap().unwrap().payload.is_none());
+        assert!(read_record(&mut reader).unwrap().is_none());
+        assert!(reader.position() as usize > full_len);
+    }
+}
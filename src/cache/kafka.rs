@@ -0,0 +1,854 @@
+use futures::Future;
+use rdkafka::client::EmptyContext;
+use rdkafka::config::{ClientConfig, TopicConfig};
+use rdkafka::consumer::{BaseConsumer, Consumer, EmptyConsumerContext};
+use rdkafka::producer::{DeliveryFuture, FutureProducer};
+use rdkafka::error::KafkaError;
+use rdkafka::message::Message;
+use rdkafka::topic_partition_list::{Offset, TopicPartitionList};
+use serde_cbor;
+
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicIsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use admin::{create_admin, create_topic, describe_configs_supported, describe_topic_config};
+use cache::{LastUpdates, ReplicaBackend, ReplicaCacheUpdate, ReplicaPositions, UpdateReceiver, WrappedKey,
+            WriteCounters};
+use cache::crypto::Cipher;
+use config::CachingConfig;
+use error::*;
+
+
+//
+// ********* KEY SOURCES **********
+//
+
+/// The instances with a live record of each key in the replica topic. Every record is keyed by
+/// the instance that wrote it, but compaction only replaces the records with the same key: when
+/// an instance writes a key, it first writes tombstones for the records of the other instances,
+/// so that a single record of each entry is left.
+#[derive(Default)]
+struct KeySources {
+    by_key: HashMap<WrappedKey, HashSet<Option<String>>>,
+}
+
+impl KeySources {
+    fn from_positions(positions: &[(WrappedKey, Vec<Option<String>>)]) -> KeySources {
+        let by_key = positions.iter()
+            .map(|&(ref key, ref sources)| (key.clone(), sources.iter().cloned().collect()))
+            .collect();
+        KeySources { by_key: by_key }
+    }
+
+    fn to_positions(&self) -> Vec<(WrappedKey, Vec<Option<String>>)> {
+        self.by_key.iter()
+            .map(|(key, sources)| (key.clone(), sources.iter().cloned().collect()))
+            .collect()
+    }
+
+    /// Records a message read from or written to the topic: a live record or a tombstone.
+    fn record(&mut self, key: &WrappedKey, live: bool) {
+        let entry_key = key.with_source(None);
+        let source = key.source().map(|source| source.to_owned());
+        if live {
+            self.by_key.entry(entry_key).or_insert_with(HashSet::new).insert(source);
+            return;
+        }
+        let no_sources_left = match self.by_key.get_mut(&entry_key) {
+            Some(sources) => {
+                sources.remove(&source);
+                sources.is_empty()
+            },
+            None => false,
+        };
+        if no_sources_left {
+            self.by_key.remove(&entry_key);
+        }
+    }
+
+    /// Returns the keys of the records of the same entry written by the other instances, and
+    /// forgets them.
+    fn take_other_sources(&mut self, key: &WrappedKey) -> Vec<WrappedKey> {
+        let entry_key = key.with_source(None);
+        let sources = match self.by_key.get_mut(&entry_key) {
+            Some(sources) => sources,
+            None => return Vec::new(),
+        };
+        let others = sources.iter()
+            .filter(|&source| source.as_ref().map(|s| s.as_str()) != key.source())
+            .cloned()
+            .collect::<Vec<_>>();
+        for source in &others {
+            sources.remove(source);
+        }
+        others.iter()
+            .map(|source| entry_key.with_source(source.as_ref().map(|s| s.as_str())))
+            .collect()
+    }
+}
+
+/// CRC-32 (IEEE) of the data, the hash used by the consistent partitioner of librdkafka.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+//
+// ********* REPLICA WRITER **********
+//
+
+/// Maximum number of writes waiting for the delivery confirmation. When the limit is reached,
+/// new writes block until some of the pending writes are confirmed.
+const MAX_IN_FLIGHT_WRITES: usize = 10000;
+/// Number of times a write is attempted before giving up.
+const MAX_WRITE_ATTEMPTS: u32 = 3;
+
+/// A write sent to Kafka, waiting for its delivery report.
+struct PendingWrite {
+    key: Vec<u8>,
+    payload: Option<Vec<u8>>,
+    partition: Option<i32>,
+    delivery: DeliveryFuture,
+    sequence: u64,
+}
+
+/// The sequence number of the last write of every key with a write waiting for its delivery
+/// report. A failed write is only retried if no later write of the same key has been sent:
+/// otherwise the retry would overwrite the newer value in the compacted topic. The lock is held
+/// while producing, so that the writes of a key are produced in sequence order.
+#[derive(Default)]
+struct LastWrites {
+    next_sequence: u64,
+    by_key: HashMap<Vec<u8>, u64>,
+}
+
+pub struct ReplicaWriter {
+    topic_name: String,
+    instance_id: String,
+    producer: FutureProducer<EmptyContext>,
+    pending: SyncSender<PendingWrite>,
+    counters: Arc<WriteCounters>,
+    cipher: Option<Arc<Cipher>>,
+    last_writes: Arc<Mutex<LastWrites>>,
+    key_sources: Arc<Mutex<KeySources>>,
+    partition_count: Option<u32>,
+}
+
+impl ReplicaWriter {
+    fn new(brokers: &str, topic_name: &str, instance_id: &str, counters: Arc<WriteCounters>,
+           cipher: Option<Arc<Cipher>>, key_sources: Arc<Mutex<KeySources>>, partition_count: Option<u32>)
+            -> Result<ReplicaWriter> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("compression.codec", "gzip")
+            .set("message.max.bytes", "10000000")
+            .set("queue.buffering.max.ms", "100")  // Batch the writes of each refresh
+            .create::<FutureProducer<_>>()
+            .chain_err(|| "Producer creation error")?;
+
+        let (sender, receiver) = mpsc::sync_channel(MAX_IN_FLIGHT_WRITES);
+        let last_writes = Arc::new(Mutex::new(LastWrites::default()));
+        let producer_clone = producer.clone();
+        let topic_name_clone = topic_name.to_owned();
+        let counters_clone = counters.clone();
+        let last_writes_clone = last_writes.clone();
+        thread::Builder::new()
+            .name("Replica delivery".into())
+            .spawn(move || delivery_loop(&producer_clone, &topic_name_clone, receiver, &counters_clone,
+                                         &last_writes_clone))
+            .chain_err(|| "Failed to start replica delivery thread")?;
+
+        let writer = ReplicaWriter {
+            topic_name: topic_name.to_owned(),
+            instance_id: instance_id.to_owned(),
+            producer: producer,
+            pending: sender,
+            counters: counters,
+            cipher: cipher,
+            last_writes: last_writes,
+            key_sources: key_sources,
+            partition_count: partition_count,
+        };
+
+        Ok(writer)
+    }
+
+    /// Writes the update to the replica topic, keyed by this instance. A `None` payload is
+    /// written as a tombstone (a message with a null payload): once the topic is compacted, the
+    /// key will disappear. The records of the same entry written by other instances are replaced
+    /// by tombstones first. The delivery is confirmed asynchronously, and failed writes are
+    /// retried. If encryption is enabled, both the key and the payload are encrypted.
+    pub fn write_update(&self, key: &WrappedKey, payload: Option<&[u8]>) -> Result<()> {
+        let own_key = key.with_source(Some(&self.instance_id));
+        let partition = self.partition(key)?;
+        // Held while writing, so that the tombstones are written before the update
+        let mut key_sources = self.key_sources.lock()
+            .map_err(|_| ErrorKind::PoisonError("replica key sources".to_owned()))?;
+        for other_key in key_sources.take_other_sources(&own_key) {
+            self.send(&other_key, None, partition)?;
+        }
+        self.send(&own_key, payload, partition)?;
+        key_sources.record(&own_key, payload.is_some());
+        Ok(())
+    }
+
+    fn encode_key(&self, key: &WrappedKey) -> Result<Vec<u8>> {
+        let serialized_key = serde_cbor::to_vec(key)
+            .chain_err(|| "Failed to serialize key")?;
+        match self.cipher {
+            Some(ref cipher) => cipher.encrypt_deterministic(&serialized_key)
+                .chain_err(|| "Failed to encrypt key"),
+            None => Ok(serialized_key),
+        }
+    }
+
+    /// All the records of an entry go to the same partition, whatever instance wrote them, so
+    /// that they are read in the order they were written. The partition is chosen like the
+    /// default partitioner does for the key without the source.
+    fn partition(&self, key: &WrappedKey) -> Result<Option<i32>> {
+        match self.partition_count {
+            Some(count) if count > 0 => {
+                let entry_key = self.encode_key(&key.with_source(None))?;
+                Ok(Some((crc32(&entry_key) % count) as i32))
+            },
+            _ => Ok(None),
+        }
+    }
+
+    fn send(&self, key: &WrappedKey, payload: Option<&[u8]>, partition: Option<i32>) -> Result<()> {
+        let serialized_key = self.encode_key(key)?;
+        let payload = match (self.cipher.as_ref(), payload) {
+            (Some(cipher), Some(payload)) => Some(cipher.encrypt(payload).chain_err(|| "Failed to encrypt payload")?),
+            (_, payload) => payload.map(|p| p.to_vec()),
+        };
+        trace!("Serialized update size: key={:.3}KB value={:.3}KB",
+            (serialized_key.len() as f64 / 1000f64),
+            (payload.as_ref().map(|p| p.len()).unwrap_or(0) as f64 / 1000f64));
+        let (delivery, sequence) = {
+            let mut last_writes = self.last_writes.lock()
+                .map_err(|_| ErrorKind::PoisonError("replica last writes".to_owned()))?;
+            let delivery = self.producer.send_copy(self.topic_name.as_str(), partition,
+                                                   payload.as_ref().map(|p| p.as_slice()), Some(&serialized_key), None)
+                .chain_err(|| "Failed to produce message")?;
+            last_writes.next_sequence += 1;
+            let sequence = last_writes.next_sequence;
+            last_writes.by_key.insert(serialized_key.clone(), sequence);
+            (delivery, sequence)
+        };
+        self.counters.written.fetch_add(1, Ordering::Relaxed);
+        self.counters.in_flight.fetch_add(1, Ordering::Relaxed);
+        let pending = PendingWrite {
+            key: serialized_key,
+            payload: payload,
+            partition: partition,
+            delivery: delivery,
+            sequence: sequence,
+        };
+        self.pending.send(pending)
+            .chain_err(|| "The replica delivery thread terminated")
+    }
+}
+
+/// Waits for the delivery reports of the pending writes, in order, retrying the failed ones
+/// unless a later write of the same key has been sent in the meantime.
+fn delivery_loop(producer: &FutureProducer<EmptyContext>, topic_name: &str,
+                 pending_writes: Receiver<PendingWrite>, counters: &WriteCounters,
+                 last_writes: &Mutex<LastWrites>) {
+    for pending in pending_writes.iter() {
+        let mut delivery = pending.delivery;
+        let mut attempt = 1;
+        loop {
+            let error = match delivery_error(delivery) {
+                Some(error) => error,
+                None => {
+                    counters.delivered.fetch_add(1, Ordering::Relaxed);
+                    break;
+                }
+            };
+            if attempt >= MAX_WRITE_ATTEMPTS {
+                error!("Write to replica topic failed after {} attempts: {}", attempt, error);
+                counters.failed.fetch_add(1, Ordering::Relaxed);
+                break;
+            }
+            // Held until the retry is produced
+            let writes = match last_writes.lock() {
+                Ok(writes) => writes,
+                Err(_) => panic!("Poison error"),
+            };
+            if writes.by_key.get(&pending.key) != Some(&pending.sequence) {
+                debug!("Write to replica topic failed, not retrying as the key was written again: {}", error);
+                break;
+            }
+            warn!("Write to replica topic failed, retrying: {}", error);
+            counters.retried.fetch_add(1, Ordering::Relaxed);
+            attempt += 1;
+            delivery = match producer.send_copy(topic_name, pending.partition, pending.payload.as_ref().map(|p| p.as_slice()),
+                                                Some(&pending.key), None) {
+                Ok(delivery) => delivery,
+                Err(e) => {
+                    error!("Write to replica topic failed, can't retry: {}", e);
+                    counters.failed.fetch_add(1, Ordering::Relaxed);
+                    break;
+                }
+            };
+        }
+        match last_writes.lock() {
+            Ok(mut writes) => {
+                if writes.by_key.get(&pending.key) == Some(&pending.sequence) {
+                    writes.by_key.remove(&pending.key);
+                }
+            },
+            Err(_) => panic!("Poison error"),
+        };
+        counters.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Waits for the delivery report, returning the error if the message wasn't delivered.
+fn delivery_error(delivery: DeliveryFuture) -> Option<String> {
+    match delivery.wait() {
+        Ok(report) => match *report.result() {
+            Ok(_) => None,
+            Err(ref e) => Some(e.to_string()),
+        },
+        Err(_) => Some("delivery report not received".to_owned()),
+    }
+}
+
+//
+// ********* REPLICA READER **********
+//
+
+type ReplicaConsumer = BaseConsumer<EmptyConsumerContext>;
+
+pub struct ReplicaReader {
+    consumer: ReplicaConsumer,
+    brokers: String,
+    topic_name: String,
+    instance_id: String,
+    positions: Arc<Mutex<HashMap<i32, i64>>>,  // Next offset to apply, for each partition
+    lag: Arc<AtomicIsize>,
+    cipher: Option<Arc<Cipher>>,
+    key_sources: Arc<Mutex<KeySources>>,
+}
+
+impl ReplicaReader {
+    fn new(brokers: &str, topic_name: &str, instance_id: &str, cipher: Option<Arc<Cipher>>,
+           key_sources: Arc<Mutex<KeySources>>) -> Result<ReplicaReader> {
+        let consumer: ReplicaConsumer = ClientConfig::new()
+            .set("group.id", &format!("kafka_web_replica_reader_{}", instance_id))
+            .set("bootstrap.servers", brokers)
+            .set("session.timeout.ms", "6000")
+            .set("enable.auto.commit", "false")
+            //.set("api.version.request", "true")
+            .set_default_topic_config(
+                TopicConfig::new()
+                .set("auto.offset.reset", "smallest")
+                .finalize())
+            .create()
+            .chain_err(|| "Consumer creation failed")?;
+
+        Ok(ReplicaReader {
+            consumer: consumer,
+            brokers: brokers.to_owned(),
+            topic_name: topic_name.to_owned(),
+            instance_id: instance_id.to_owned(),
+            positions: Arc::new(Mutex::new(HashMap::new())),
+            lag: Arc::new(AtomicIsize::new(-1)),
+            cipher: cipher,
+            key_sources: key_sources,
+        })
+    }
+
+    /// Loads the state from the replica topic. If the positions of a snapshot are provided, only
+    /// the messages following those positions are read.
+    pub fn load_state(&mut self, receiver: &UpdateReceiver, from: Option<&ReplicaPositions>) -> Result<()> {
+        info!("Started creating state");
+        if let Some(positions) = from {
+            match self.key_sources.lock() {
+                Ok(mut key_sources) => *key_sources = KeySources::from_positions(&positions.key_sources),
+                Err(_) => bail!(ErrorKind::PoisonError("replica key sources".to_owned())),
+            };
+        }
+        match self.last_message_per_key(from) {
+            Err(e) => format_error_chain!(e),
+            Ok(state) => {
+                for (w_key, payload) in state.into_ordered() {
+                    match self.key_sources.lock() {
+                        Ok(mut key_sources) => key_sources.record(&w_key, payload.is_some()),
+                        Err(_) => bail!(ErrorKind::PoisonError("replica key sources".to_owned())),
+                    };
+                    if let Err(e) = apply_update(receiver, &w_key, payload.as_ref().map(|p| p.as_slice())) {
+                        format_error_chain!(e);
+                    }
+                }
+            }
+        }
+        info!("State creation terminated");
+        Ok(())
+    }
+
+    fn partition_count(&self) -> Result<Option<usize>> {
+        let metadata = self.consumer.fetch_metadata(5000)
+            .chain_err(|| "Failed to fetch metadata")?;
+        let partition_count = metadata.topics().iter()
+            .find(|m| m.name() == self.topic_name)
+            .map(|topic_metadata| topic_metadata.partitions().len());
+        Ok(partition_count)
+    }
+
+    /// Starts consuming the topic, either from the beginning or from the positions of a snapshot.
+    /// Partitions missing from the snapshot are read from the beginning.
+    fn start_consumer(&mut self, partition_count: usize, from: Option<&ReplicaPositions>) -> Result<()> {
+        match from {
+            Some(positions) => {
+                let mut partitions = TopicPartitionList::new();
+                for partition in 0..(partition_count as i32) {
+                    let offset = match positions.offsets.get(&partition) {
+                        Some(&offset) => Offset::Offset(offset),
+                        None => Offset::Beginning,
+                    };
+                    partitions.add_partition_with_offset(&self.topic_name, partition, offset);
+                }
+                self.consumer.assign(&partitions)
+                    .chain_err(|| "Can't assign the snapshot positions")?;
+                self.set_positions(&positions.offsets);
+            },
+            None => {
+                self.consumer.subscribe(&vec![self.topic_name.as_str()])
+                    .chain_err(|| "Can't subscribe to specified topics")?;
+            }
+        };
+        Ok(())
+    }
+
+    /// Decodes the key of the message and its payload, decrypting them if encryption is enabled.
+    fn decode_message(&self, message: &Message) -> Result<(WrappedKey, Option<Vec<u8>>)> {
+        let key_bytes = match message.key() {
+            Some(k) => k,
+            None => bail!("Empty key found"),
+        };
+        let (key_bytes, payload) = match self.cipher {
+            Some(ref cipher) => {
+                let key_bytes = cipher.decrypt(key_bytes).chain_err(|| "Failed to decrypt key")?;
+                let payload = match message.payload() {
+                    Some(payload) => Some(cipher.decrypt(payload).chain_err(|| "Failed to decrypt payload")?),
+                    None => None,
+                };
+                (key_bytes, payload)
+            },
+            None => (key_bytes.to_vec(), message.payload().map(|p| p.to_vec())),
+        };
+        let wrapped_key = serde_cbor::from_slice::<WrappedKey>(&key_bytes)
+            .chain_err(|| "Failed to decode wrapped key")?;
+        Ok((wrapped_key, payload))
+    }
+
+    fn last_message_per_key(&mut self, from: Option<&ReplicaPositions>) -> Result<LastUpdates> {
+        let mut eof_set = HashSet::new();
+        let mut state = LastUpdates::default();
+
+        let partition_count = match self.partition_count()? {
+            Some(count) => count,
+            None => {
+                warn!("No replicator topic found ({} {})", self.brokers, self.topic_name);
+                self.start_consumer(0, None)?;
+                return Ok(state);
+            }
+        };
+        self.start_consumer(partition_count, from)?;
+
+        let mut positions = HashMap::new();
+        while eof_set.len() < partition_count {
+            match self.consumer.poll(1000) {
+                Ok(Some(m)) => {
+                    positions.insert(m.partition(), m.offset() + 1);
+                    match self.decode_message(&m).chain_err(|| "Failed to decode message") {
+                        Ok((wrapped_key, payload)) => { state.insert(wrapped_key, payload); () },
+                        Err(e) => format_error_chain!(e),
+                    };
+                },
+                Ok(None) => {},
+                Err(KafkaError::PartitionEOF(p)) => { eof_set.insert(p); () },
+                Err(e) => error!("Error while reading from Kafka: {}", e),
+            };
+        }
+        // Snapshots are only taken after the state has been loaded, so the positions can be
+        // updated before the state is applied.
+        self.set_positions(&positions);
+        self.lag.store(0, Ordering::Relaxed);
+        Ok(state)
+    }
+
+    fn set_positions(&self, positions: &HashMap<i32, i64>) {
+        for (&partition, &offset) in positions {
+            self.set_position(partition, offset);
+        }
+    }
+
+    fn set_position(&self, partition: i32, offset: i64) {
+        match self.positions.lock() {
+            Ok(mut positions) => positions.insert(partition, offset),
+            Err(_) => panic!("Poison error"),
+        };
+    }
+
+    /// Keeps consuming the replica topic in a background thread, applying the updates written by
+    /// the other kafka-view instances. Updates written by this instance, tombstones included, are
+    /// already in the cache and are skipped.
+    pub fn start_tailing(mut self, receiver: Box<UpdateReceiver>) -> Result<()> {
+        thread::Builder::new()
+            .name("Replica reader".into())
+            .spawn(move || self.tail(receiver.as_ref()))
+            .chain_err(|| "Failed to start replica reader thread")?;
+        Ok(())
+    }
+
+    fn tail(&mut self, receiver: &UpdateReceiver) {
+        info!("Tailing replica topic {}", self.topic_name);
+        let mut last_lag_check = Instant::now();
+        loop {
+            match self.consumer.poll(1000) {
+                Ok(Some(m)) => {
+                    let result = self.decode_message(&m)
+                        .chain_err(|| "Failed to decode message")
+                        .and_then(|(w_key, payload)| {
+                            apply_tailed_update(receiver, &self.instance_id, &self.key_sources, &w_key,
+                                                payload.as_ref().map(|p| p.as_slice()))
+                        });
+                    if let Err(e) = result {
+                        format_error_chain!(e);
+                    }
+                    self.set_position(m.partition(), m.offset() + 1);
+                },
+                Ok(None) | Err(KafkaError::PartitionEOF(_)) => {},
+                Err(e) => error!("Error while reading from Kafka: {}", e),
+            };
+            if last_lag_check.elapsed() > Duration::from_secs(10) {
+                match self.compute_lag() {
+                    Ok(lag) => {
+                        debug!("Replica reader is {} messages behind", lag);
+                        self.lag.store(lag as isize, Ordering::Relaxed);
+                    },
+                    Err(e) => format_error_chain!(e),
+                };
+                last_lag_check = Instant::now();
+            }
+        }
+    }
+
+    /// Returns the number of messages between the current position and the end of the topic.
+    fn compute_lag(&self) -> Result<i64> {
+        let metadata = self.consumer.fetch_metadata(5000)
+            .chain_err(|| "Failed to fetch metadata")?;
+        let topic_metadata = match metadata.topics().iter().find(|m| m.name() == self.topic_name) {
+            Some(topic_metadata) => topic_metadata,
+            None => bail!("No replicator topic found ({} {})", self.brokers, self.topic_name),
+        };
+        let positions = match self.positions.lock() {
+            Ok(positions) => positions.clone(),
+            Err(_) => panic!("Poison error"),
+        };
+        let mut lag = 0;
+        for partition in topic_metadata.partitions() {
+            let (low, high) = self.consumer.fetch_watermarks(&self.topic_name, partition.id(), 5000)
+                .chain_err(|| format!("Failed to fetch watermarks for partition {}", partition.id()))?;
+            let position = positions.get(&partition.id()).cloned().unwrap_or(low);
+            lag += cmp::max(high - position, 0);
+        }
+        Ok(lag)
+    }
+}
+
+/// Applies an update read while tailing the topic, unless this instance wrote it: replaying an
+/// old update of this instance could undo a more recent one.
+fn apply_tailed_update(receiver: &UpdateReceiver, instance_id: &str, key_sources: &Mutex<KeySources>,
+                       w_key: &WrappedKey, payload: Option<&[u8]>) -> Result<()> {
+    match key_sources.lock() {
+        Ok(mut key_sources) => key_sources.record(w_key, payload.is_some()),
+        Err(_) => bail!(ErrorKind::PoisonError("replica key sources".to_owned())),
+    };
+    if w_key.source() == Some(instance_id) {
+        return Ok(());
+    }
+    apply_update(receiver, w_key, payload)
+}
+
+fn apply_update(receiver: &UpdateReceiver, w_key: &WrappedKey, payload: Option<&[u8]>) -> Result<()> {
+    match payload {
+        Some(payload) => receiver.receive_update(w_key.cache_name(), ReplicaCacheUpdate::Set {
+            key: w_key,
+            payload: payload,
+        }),
+        None => receiver.receive_update(w_key.cache_name(), ReplicaCacheUpdate::Delete {
+            key: w_key
+        }),
+    }
+}
+
+//
+// ********* TOPIC PROVISIONING **********
+//
+
+/// Topic configuration used when the replica topic is created.
+const REPLICA_TOPIC_CONFIG: &'static [(&'static str, &'static str)] = &[
+    ("cleanup.policy", "compact"),
+    ("min.cleanable.dirty.ratio", "0.1"),
+    ("segment.bytes", "104857600"),
+];
+
+/// Creates the replica topic if it doesn't exist and `create_topic` is set. If it exists, checks
+/// that it is compacted: without compaction, the topic grows without bound and loading the state
+/// gets slower at every restart. Returns an error if the topic is not compacted and
+/// `strict_topic_check` is set. Brokers older than 0.11 can't describe the topic configuration:
+/// the check is skipped with a warning.
+pub fn ensure_replica_topic(brokers: &str, topic_name: &str, config: &CachingConfig) -> Result<()> {
+    let timeout = Duration::from_secs(30);
+    let consumer = ClientConfig::new()
+        .set("bootstrap.servers", brokers)
+        .create::<ReplicaConsumer>()
+        .chain_err(|| "Consumer creation failed")?;
+    let metadata = consumer.fetch_metadata(30000)
+        .chain_err(|| "Failed to fetch metadata")?;
+    let topic_exists = metadata.topics().iter().any(|t| t.name() == topic_name && t.error().is_none());
+    let admin = create_admin(brokers)?;
+
+    if !topic_exists {
+        if !config.create_topic {
+            warn!("The replica topic {} doesn't exist and create_topic is disabled", topic_name);
+            return Ok(());
+        }
+        // Small clusters might not have enough brokers for the configured replication factor
+        let replication_factor = cmp::min(config.topic_replication, metadata.brokers().len() as i32);
+        info!("Creating replica topic {} (partitions: {}, replication factor: {})",
+              topic_name, config.topic_partitions, replication_factor);
+        return create_topic(&admin, topic_name, config.topic_partitions, replication_factor,
+                            REPLICA_TOPIC_CONFIG, timeout)
+            .chain_err(|| format!("Failed to create replica topic {}", topic_name));
+    }
+
+    let topic_config = match describe_topic_config(&admin, topic_name, timeout) {
+        Ok(topic_config) => topic_config,
+        Err(e) => {
+            if let Ok(false) = describe_configs_supported(&admin, timeout) {
+                warn!("The cluster can't describe the configuration of the replica topic {}, make sure \
+                       that it has cleanup.policy=compact", topic_name);
+                return Ok(());
+            }
+            return Err(e);
+        },
+    };
+    let cleanup_policy = topic_config.iter()
+        .find(|entry| entry.name == "cleanup.policy")
+        .and_then(|entry| entry.value.clone())
+        .unwrap_or_else(|| "unknown".to_owned());
+    if cleanup_policy != "compact" {
+        let message = format!("The replica topic {} has cleanup.policy={}, but it should be \
+                               cleanup.policy=compact", topic_name, cleanup_policy);
+        if config.strict_topic_check {
+            bail!(message);
+        }
+        warn!("{}: the topic will grow without bound, and old cache entries might be deleted", message);
+    }
+    Ok(())
+}
+
+//
+// ********* KAFKA BACKEND **********
+//
+
+/// Stores the cache state in a compacted Kafka topic, shared by all the kafka-view instances
+/// using the same caching configuration.
+pub struct KafkaBackend {
+    topic_name: String,
+    instance_id: String,
+    writer: ReplicaWriter,
+    reader: Mutex<Option<ReplicaReader>>,
+    positions: Arc<Mutex<HashMap<i32, i64>>>,
+    lag: Arc<AtomicIsize>,
+    counters: Arc<WriteCounters>,
+    key_sources: Arc<Mutex<KeySources>>,
+}
+
+impl KafkaBackend {
+    /// Creates the backend. If a cipher is provided, the records are encrypted.
+    pub fn new(brokers: &str, topic_name: &str, instance_id: &str, cipher: Option<Cipher>) -> Result<KafkaBackend> {
+        let cipher = cipher.map(Arc::new);
+        let counters = Arc::new(WriteCounters::default());
+        let key_sources = Arc::new(Mutex::new(KeySources::default()));
+        let reader = ReplicaReader::new(brokers, topic_name, instance_id, cipher.clone(), key_sources.clone())
+            .chain_err(|| format!("Replica reader creation failed (brokers: {}, topic: {})", brokers, topic_name))?;
+        let partition_count = match reader.partition_count() {
+            Ok(count) => count.map(|count| count as u32),
+            Err(e) => {
+                format_error_chain!(e);
+                None
+            },
+        };
+        let writer = ReplicaWriter::new(brokers, topic_name, instance_id, counters.clone(), cipher,
+                                        key_sources.clone(), partition_count)
+            .chain_err(|| format!("Replica writer creation failed (brokers: {}, topic: {})", brokers, topic_name))?;
+        Ok(KafkaBackend {
+            topic_name: topic_name.to_owned(),
+            instance_id: instance_id.to_owned(),
+            writer: writer,
+            positions: reader.positions.clone(),
+            lag: reader.lag.clone(),
+            reader: Mutex::new(Some(reader)),
+            counters: counters,
+            key_sources: key_sources,
+        })
+    }
+}
+
+impl ReplicaBackend for KafkaBackend {
+    fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    fn write_update(&self, key: &WrappedKey, payload: Option<&[u8]>) -> Result<()> {
+        self.writer.write_update(key, payload)
+    }
+
+    fn write_counters(&self) -> &WriteCounters {
+        &self.counters
+    }
+
+    fn load_state(&self, receiver: &UpdateReceiver, from: Option<&ReplicaPositions>) -> Result<()> {
+        match self.reader.lock() {
+            Ok(mut reader) => match *reader {
+                Some(ref mut reader) => reader.load_state(receiver, from),
+                None => bail!("The replica reader is already tailing the topic"),
+            },
+            Err(_) => Err(ErrorKind::PoisonError("loading state from Kafka".to_owned()).into()),
+        }
+    }
+
+    fn start_tailing(&self, receiver: Box<UpdateReceiver>) -> Result<()> {
+        let reader = match self.reader.lock() {
+            Ok(mut reader) => reader.take(),
+            Err(_) => bail!(ErrorKind::PoisonError("starting replica reader".to_owned())),
+        };
+        match reader {
+            Some(reader) => reader.start_tailing(receiver),
+            None => bail!("The replica reader is already tailing the topic"),
+        }
+    }
+
+    fn positions(&self) -> Option<ReplicaPositions> {
+        let offsets = match self.positions.lock() {
+            Ok(positions) => positions.clone(),
+            Err(_) => panic!("Poison error"),
+        };
+        let key_sources = match self.key_sources.lock() {
+            Ok(key_sources) => key_sources.to_positions(),
+            Err(_) => panic!("Poison error"),
+        };
+        Some(ReplicaPositions {
+            topic: self.topic_name.clone(),
+            offsets: offsets,
+            key_sources: key_sources,
+        })
+    }
+
+    fn replica_lag(&self) -> Option<i64> {
+        match self.lag.load(Ordering::Relaxed) {
+            lag if lag < 0 => None,
+            lag => Some(lag as i64),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keeps the last payload of every key, like a map of the cache.
+    #[derive(Default)]
+    struct TestReceiver {
+        state: Mutex<HashMap<Vec<u8>, Vec<u8>>>,
+    }
+
+    impl UpdateReceiver for TestReceiver {
+        fn receive_update(&self, _name: &str, update: ReplicaCacheUpdate) -> Result<()> {
+            let mut state = self.state.lock().unwrap();
+            match update {
+                ReplicaCacheUpdate::Set { key, payload } => state.insert(key.serialized_key().to_vec(), payload.to_vec()),
+                ReplicaCacheUpdate::Delete { key } => state.remove(key.serialized_key()),
+            };
+            Ok(())
+        }
+    }
+
+    impl TestReceiver {
+        fn get(&self, key: &WrappedKey) -> Option<Vec<u8>> {
+            self.state.lock().unwrap().get(key.serialized_key()).cloned()
+        }
+    }
+
+    /// Returns the messages written by the instance to update the key.
+    fn write(key_sources: &Mutex<KeySources>, instance_id: &str, key: &WrappedKey, payload: Option<&[u8]>)
+            -> Vec<(WrappedKey, Option<Vec<u8>>)> {
+        let own_key = key.with_source(Some(instance_id));
+        let mut key_sources = key_sources.lock().unwrap();
+        let mut messages = key_sources.take_other_sources(&own_key).into_iter()
+            .map(|other_key| (other_key, None))
+            .collect::<Vec<_>>();
+        messages.push((own_key.clone(), payload.map(|p| p.to_vec())));
+        key_sources.record(&own_key, payload.is_some());
+        messages
+    }
+
+    fn replay(receiver: &TestReceiver, instance_id: &str, key_sources: &Mutex<KeySources>,
+              messages: &[(WrappedKey, Option<Vec<u8>>)]) {
+        for &(ref key, ref payload) in messages {
+            apply_tailed_update(receiver, instance_id, key_sources, key, payload.as_ref().map(|p| p.as_slice()))
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn own_deletes_are_not_replayed() {
+        let key = WrappedKey::new("topics".to_owned(), &"key").unwrap();
+        let (sources_a, receiver_a) = (Mutex::new(KeySources::default()), TestReceiver::default());
+        let (sources_b, receiver_b) = (Mutex::new(KeySources::default()), TestReceiver::default());
+
+        // Instance a inserts, deletes and inserts the key again
+        let mut messages = write(&sources_a, "a", &key, Some(b"v1"));
+        messages.extend(write(&sources_a, "a", &key, None));
+        messages.extend(write(&sources_a, "a", &key, Some(b"v2")));
+        receiver_a.receive_update("topics", ReplicaCacheUpdate::Set { key: &key, payload: b"v2" }).unwrap();
+
+        // The tail of a skips its own writes, the tail of b applies them
+        replay(&receiver_a, "a", &sources_a, &messages);
+        replay(&receiver_b, "b", &sources_b, &messages);
+        assert_eq!(receiver_a.get(&key), Some(b"v2".to_vec()));
+        assert_eq!(receiver_b.get(&key), Some(b"v2".to_vec()));
+
+        // Instance b deletes the key, removing the record of a as well
+        let messages = write(&sources_b, "b", &key, None);
+        assert_eq!(messages, vec![(key.with_source(Some("a")), None), (key.with_source(Some("b")), None)]);
+        receiver_b.receive_update("topics", ReplicaCacheUpdate::Delete { key: &key }).unwrap();
+        replay(&receiver_a, "a", &sources_a, &messages);
+        replay(&receiver_b, "b", &sources_b, &messages);
+        assert_eq!(receiver_a.get(&key), None);
+        assert_eq!(receiver_b.get(&key), None);
+        assert!(sources_a.lock().unwrap().by_key.is_empty());
+    }
+
+    #[test]
+    fn partition_hash_is_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+}
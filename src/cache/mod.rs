@@ -0,0 +1,964 @@
+use chrono::{DateTime, TimeZone, Timelike, UTC};
+use serde::de::Deserialize;
+use serde::ser::Serialize;
+use serde_cbor;
+
+use std::cmp;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use admin::ConfigValue;
+use config::CachingConfig;
+use election::Leadership;
+use error::*;
+use events::ClusterEvent;
+use metadata::{Broker, BrokerId, ClusterId, Group, Partition, TopicName, upgrade_brokers_v1, upgrade_brokers_v2,
+               upgrade_group_v1};
+use metrics::BrokerMetrics;
+
+mod activity;
+mod crypto;
+mod export;
+mod file_log;
+mod kafka;
+mod schema;
+mod snapshot;
+mod store;
+
+pub use self::crypto::Cipher;
+pub use self::file_log::FileLogBackend;
+pub use self::kafka::{KafkaBackend, ensure_replica_topic};
+pub use self::schema::{Migrations, WrappedKey};
+pub use self::store::{ClusterKey, Selection};
+use self::activity::MapActivity;
+use self::schema::ValueEnvelope;
+use self::store::{IndexFn, Store};
+
+
+#[derive(Debug)]
+pub enum ReplicaCacheUpdate<'a> {
+    Set { key: &'a WrappedKey, payload: &'a[u8] },
+    Delete { key: &'a WrappedKey }
+}
+
+pub trait UpdateReceiver: Send + 'static {
+    fn receive_update(&self, name: &str, update: ReplicaCacheUpdate) -> Result<()>;
+}
+
+/// The last update of every key read from a backend, in the order the updates were written.
+/// Replaying them in that order makes the most recent update win when records with different
+/// wrapped keys decode to the same entry, for example before and after a key upgrade.
+#[derive(Default)]
+pub struct LastUpdates {
+    next_position: u64,
+    updates: HashMap<WrappedKey, (u64, Option<Vec<u8>>)>,
+}
+
+impl LastUpdates {
+    pub fn insert(&mut self, key: WrappedKey, payload: Option<Vec<u8>>) {
+        self.updates.insert(key, (self.next_position, payload));
+        self.next_position += 1;
+    }
+
+    /// Returns the updates, from the oldest to the most recent.
+    pub fn into_ordered(self) -> Vec<(WrappedKey, Option<Vec<u8>>)> {
+        let mut updates = self.updates.into_iter().collect::<Vec<_>>();
+        updates.sort_by_key(|&(_, (position, _))| position);
+        updates.into_iter()
+            .map(|(key, (_, payload))| (key, payload))
+            .collect()
+    }
+}
+
+/// The position reached in the replica topic, as next offset to read for each partition.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReplicaPositions {
+    pub topic: String,
+    pub offsets: HashMap<i32, i64>,
+    /// The instances with a live record of each key at those positions.
+    #[serde(default)]
+    pub key_sources: Vec<(WrappedKey, Vec<Option<String>>)>,
+}
+
+/// Counters of the writes to the backend.
+#[derive(Default)]
+pub struct WriteCounters {
+    pub written: AtomicUsize,
+    pub delivered: AtomicUsize,
+    pub failed: AtomicUsize,
+    pub retried: AtomicUsize,
+    pub in_flight: AtomicUsize,
+    /// Inserts skipped because the value didn't change.
+    pub coalesced: AtomicUsize,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct WriteStats {
+    pub written: usize,
+    pub delivered: usize,
+    pub failed: usize,
+    pub retried: usize,
+    pub in_flight: usize,
+    pub coalesced: usize,
+}
+
+impl WriteCounters {
+    pub fn stats(&self) -> WriteStats {
+        WriteStats {
+            written: self.written.load(Ordering::Relaxed),
+            delivered: self.delivered.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            retried: self.retried.load(Ordering::Relaxed),
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+            coalesced: self.coalesced.load(Ordering::Relaxed),
+        }
+    }
+}
+
+//
+// ********** BACKEND **********
+//
+
+/// Where the updates to the cache are persisted, and where the state is loaded from at startup.
+pub trait ReplicaBackend: Send + Sync + 'static {
+    /// Persists an update. A `None` payload deletes the key.
+    fn write_update(&self, key: &WrappedKey, payload: Option<&[u8]>) -> Result<()>;
+
+    /// Counters of the writes done by this instance.
+    fn write_counters(&self) -> &WriteCounters;
+
+    /// Sends the last stored update for every key to the receiver. If positions are provided,
+    /// only the updates following those positions are sent.
+    fn load_state(&self, receiver: &UpdateReceiver, from: Option<&ReplicaPositions>) -> Result<()>;
+
+    /// After the state has been loaded, keeps sending to the receiver the updates written by
+    /// other kafka-view instances sharing the same backend.
+    fn start_tailing(&self, _receiver: Box<UpdateReceiver>) -> Result<()> {
+        Ok(())
+    }
+
+    /// The positions of all the updates received so far, if the backend supports snapshots.
+    fn positions(&self) -> Option<ReplicaPositions> {
+        None
+    }
+
+    /// Number of updates written by other instances that haven't been received yet, if known.
+    fn replica_lag(&self) -> Option<i64> {
+        None
+    }
+
+    /// Identifies this kafka-view instance as the source of the updates it writes.
+    fn instance_id(&self) -> &str;
+}
+
+// pub trait ReplicatedCache {
+//     type Key: Serialize + Deserialize;
+//     type Value: Serialize + Deserialize;
+//
+//     fn new(SharedReplicaWriter, &str) -> Self;
+//     fn name(&self) -> &str;
+//     fn insert(&self, Self::Key, Self::Value) -> Result<Arc<Self::Value>>;
+//     fn get(&self, &Self::Key) -> Option<Arc<Self::Value>>;
+//     fn keys(&self) -> Vec<Self::Key>;
+// }
+
+
+// impl<V> ValueContainer<V>
+//   where K: Serialize + Deserialize {
+//     fn new(id: i32, value: V) -> WrappedKey<V> {
+//         ValueContainer {
+//             id: id,
+//             value: value,
+//         }
+//     }
+// }
+
+//
+// ********** REPLICATEDMAP **********
+//
+
+/// When an entry was last updated, and by which kafka-view instance. Both are unknown for
+/// entries written by older versions of kafka-view.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EntryInfo {
+    pub updated: Option<DateTime<UTC>>,
+    pub source: Option<String>,
+}
+
+impl EntryInfo {
+    fn from_envelope(envelope: &ValueEnvelope) -> EntryInfo {
+        let updated = envelope.timestamp
+            .map(|ts| UTC.timestamp(ts / 1000, ((ts % 1000) * 1_000_000) as u32));
+        EntryInfo {
+            updated: updated,
+            source: envelope.source.clone(),
+        }
+    }
+
+    /// Age of the entry in seconds, if its update time is known.
+    pub fn age(&self) -> Option<i64> {
+        self.updated.map(|updated| UTC::now().signed_duration_since(updated).num_seconds())
+    }
+}
+
+/// A change to the content of a `ReplicatedMap`, either done locally or received from the
+/// backend.
+#[derive(Debug, Clone)]
+pub enum CacheEvent<K, V> {
+    Insert { key: K, value: V },
+    Update { key: K, old: V, new: V },
+    Delete { key: K, old: V },
+}
+
+impl<K, V> CacheEvent<K, V> {
+    pub fn key(&self) -> &K {
+        match *self {
+            CacheEvent::Insert { ref key, .. } => key,
+            CacheEvent::Update { ref key, .. } => key,
+            CacheEvent::Delete { ref key, .. } => key,
+        }
+    }
+}
+
+/// Size and activity statistics of a map.
+#[derive(Serialize, Debug, Clone)]
+pub struct MapStats {
+    pub name: String,
+    pub entries: usize,
+    /// Approximate size of the entries, in serialized bytes.
+    pub approx_size: usize,
+    pub memory_limit: Option<usize>,
+    /// Entries evicted by this instance since it started.
+    pub evicted: usize,
+    /// Writes per second to the backend, over the last minute.
+    pub write_rate: f64,
+    pub last_update: Option<DateTime<UTC>>,
+    /// Updates received from the backend since this instance started.
+    pub replayed: usize,
+}
+
+/// Iterator over the keys and values of a map, see `lock_iter`.
+pub type MapIter<'a, K, V> = Box<Iterator<Item=(&'a K, &'a V)> + 'a>;
+
+pub struct ReplicatedMap<K, V>
+        where K: Eq + Hash + Clone + Serialize + Deserialize + ClusterKey,
+              V: Clone + PartialEq + Serialize + Deserialize {
+    name: String,
+    map: Arc<RwLock<Store<K, V>>>,
+    subscribers: Arc<Mutex<Vec<Sender<CacheEvent<K, V>>>>>,
+    backend: Arc<ReplicaBackend>,
+    migrations: Arc<Migrations>,
+    ttl: Option<Duration>,
+    rewrite_interval: Option<Duration>,
+    memory_limit: Option<usize>,
+    evict_by: Option<&'static str>,
+    evicted: Arc<AtomicUsize>,
+    activity: Arc<Mutex<MapActivity>>,
+}
+
+impl<K, V> ReplicatedMap<K, V> where K: Eq + Hash + Clone + Serialize + Deserialize + ClusterKey,
+                                     V: Clone + PartialEq + Serialize + Deserialize {
+    pub fn new(name: &str, backend: Arc<ReplicaBackend>) -> ReplicatedMap<K, V> {
+        ReplicatedMap::with_migrations(name, backend, Migrations::new())
+    }
+
+    /// Creates a map whose stored records are upgraded using the given migrations.
+    pub fn with_migrations(name: &str, backend: Arc<ReplicaBackend>, migrations: Migrations) -> ReplicatedMap<K, V> {
+        ReplicatedMap {
+            name: name.to_owned(),
+            map: Arc::new(RwLock::new(Store::new())),
+            subscribers: Arc::new(Mutex::new(Vec::new())),
+            backend: backend,
+            migrations: Arc::new(migrations),
+            ttl: None,
+            rewrite_interval: None,
+            memory_limit: None,
+            evict_by: None,
+            evicted: Arc::new(AtomicUsize::new(0)),
+            activity: Arc::new(Mutex::new(MapActivity::new())),
+        }
+    }
+
+    /// Entries not updated for longer than the TTL are removed from the map by `expire`.
+    pub fn with_ttl(mut self, ttl: Option<Duration>) -> ReplicatedMap<K, V> {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Inserting a value equal to the stored one doesn't write to the backend, unless the stored
+    /// value is older than the rewrite interval. Without a rewrite interval, every insert is
+    /// written.
+    pub fn with_rewrite_interval(mut self, interval: Option<Duration>) -> ReplicatedMap<K, V> {
+        self.rewrite_interval = interval;
+        self
+    }
+
+    /// When the approximate size of the map exceeds the limit (in bytes), `evict` removes the
+    /// least recently updated entries. If an index is specified, the entries sharing the same
+    /// indexed value are evicted together.
+    pub fn with_memory_limit(mut self, limit: Option<usize>, evict_by: Option<&'static str>) -> ReplicatedMap<K, V> {
+        self.memory_limit = limit;
+        self.evict_by = evict_by;
+        self
+    }
+
+    /// Adds a secondary index on the value returned by `index_fn`, to be used with
+    /// `Selection::Index`. Indexes have to be added before any entry is inserted.
+    pub fn with_index(self, name: &'static str, index_fn: IndexFn<K>) -> ReplicatedMap<K, V> {
+        match self.map.write() {
+            Ok(mut store) => store.add_index(name, index_fn),
+            Err(_) => panic!("Poison error"),
+        };
+        self
+    }
+
+    pub fn alias(&self) -> ReplicatedMap<K, V> {
+        ReplicatedMap {
+            name: self.name.clone(),
+            map: self.map.clone(),
+            subscribers: self.subscribers.clone(),
+            backend: self.backend.clone(),
+            migrations: self.migrations.clone(),
+            ttl: self.ttl,
+            rewrite_interval: self.rewrite_interval,
+            memory_limit: self.memory_limit,
+            evict_by: self.evict_by,
+            evicted: self.evicted.clone(),
+            activity: self.activity.clone(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+
+    /// Returns a channel receiving all the changes to the map from now on. The subscription is
+    /// dropped when the receiver is dropped.
+    pub fn subscribe(&self) -> Receiver<CacheEvent<K, V>> {
+        let (sender, receiver) = mpsc::channel();
+        match self.subscribers.lock() {
+            Ok(mut subscribers) => subscribers.push(sender),
+            Err(_) => panic!("Poison error"),
+        };
+        receiver
+    }
+
+    fn notify(&self, event: CacheEvent<K, V>) {
+        match self.subscribers.lock() {
+            Ok(mut subscribers) => {
+                if subscribers.is_empty() {
+                    return;
+                }
+                subscribers.retain(|sender| sender.send(event.clone()).is_ok());
+            },
+            Err(_) => panic!("Poison error"),
+        };
+    }
+
+    pub fn keys(&self) -> Vec<K> {
+        self.lock_iter(|iter| iter.map(|(k, _)| k.clone()).collect::<Vec<_>>())
+    }
+
+    /// Returns the clusters having at least one entry in the map.
+    pub fn clusters(&self) -> Vec<ClusterId> {
+        match self.map.read() {
+            Ok(store) => store.clusters(),
+            Err(_) => panic!("Poison error"),
+        }
+    }
+
+    fn with_activity<F, R>(&self, f: F) -> R
+            where F: FnOnce(&mut MapActivity) -> R {
+        match self.activity.lock() {
+            Ok(mut activity) => f(&mut activity),
+            Err(_) => panic!("Poison error"),
+        }
+    }
+
+    pub fn receive_update(&self, update: ReplicaCacheUpdate) -> Result<()> {
+        self.with_activity(|activity| activity.replayed += 1);
+        match update {
+            ReplicaCacheUpdate::Set { key, payload } => {
+                let envelope = ValueEnvelope::decode(payload)?;
+                let key_bytes = self.migrations.upgrade_key(key.serialized_key(), envelope.key_version)?;
+                let key = serde_cbor::from_slice::<K>(&key_bytes)
+                    .chain_err(|| "Failed to parse key")?;
+                let value_bytes = self.migrations.upgrade_value(&envelope.payload, envelope.version)?;
+                let value = serde_cbor::from_slice::<V>(&value_bytes)
+                    .chain_err(|| "Failed to parse payload")?;
+                let size = key_bytes.len() + payload.len();
+                self.sync_value_update(key, value, EntryInfo::from_envelope(&envelope), size);
+            },
+            ReplicaCacheUpdate::Delete { key } => {
+                let key = self.migrations.parse_tombstone_key::<K>(key.serialized_key())?;
+                self.sync_value_delete(&key);
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the update to the local map. The size is the approximate memory used by the
+    /// entry, as length of the serialized key and value.
+    pub fn sync_value_update(&self, key: K, value: V, info: EntryInfo, size: usize) {
+        match self.map.write() {
+            // Notifying while holding the lock keeps the events in the same order as the changes
+            Ok(mut store) => {
+                self.with_activity(|activity| activity.record_update(info.updated));
+                let event = match store.insert(key.clone(), value.clone(), info, size) {
+                    Some(old) => CacheEvent::Update { key: key, old: old, new: value },
+                    None => CacheEvent::Insert { key: key, value: value },
+                };
+                self.notify(event);
+            },
+            Err(_) => panic!("Poison error"),
+        };
+    }
+
+    pub fn sync_value_delete(&self, key: &K) -> Option<V> {
+        match self.map.write() {
+            Ok(mut store) => {
+                let old = store.remove(key);
+                if let Some(ref old) = old {
+                    self.with_activity(|activity| activity.record_update(None));
+                    self.notify(CacheEvent::Delete { key: key.clone(), old: old.clone() });
+                }
+                old
+            },
+            Err(_) => panic!("Poison error"),
+        }
+    }
+
+    fn serialize_value(&self, value: &V, info: Option<&EntryInfo>) -> Result<Vec<u8>> {
+        let payload = serde_cbor::to_vec(value)
+            .chain_err(|| "Failed to serialize value")?;
+        let updated = info.and_then(|info| info.updated).unwrap_or_else(UTC::now);
+        let source = info.and_then(|info| info.source.as_ref())
+            .map(|source| source.as_str())
+            .unwrap_or_else(|| self.backend.instance_id());
+        let timestamp = updated.timestamp() * 1000 + (updated.nanosecond() / 1_000_000) as i64;
+        self.migrations.wrap_value(payload, timestamp, source)
+    }
+
+    /// Returns true if the stored value is the same and recent enough not to be rewritten.
+    fn is_unchanged(&self, key: &K, value: &V) -> bool {
+        let rewrite_interval = match self.rewrite_interval {
+            Some(interval) => interval.as_secs() as i64,
+            None => return false,
+        };
+        match self.map.read() {
+            Ok(store) => store.get(key)
+                .map(|entry| &entry.value == value && entry.info.age().map(|age| age < rewrite_interval).unwrap_or(false))
+                .unwrap_or(false),
+            Err(_) => panic!("Poison error"),
+        }
+    }
+
+    pub fn insert(&self, key: K, value: V) -> Result<()> {
+        if self.is_unchanged(&key, &value) {
+            self.backend.write_counters().coalesced.fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        }
+        let info = EntryInfo {
+            updated: Some(UTC::now()),
+            source: Some(self.backend.instance_id().to_owned()),
+        };
+        self.insert_with_info(key, value, info)
+    }
+
+    /// Like `insert`, but keeping the specified update time and source.
+    pub fn insert_with_info(&self, key: K, value: V, info: EntryInfo) -> Result<()> {
+        let wrapped_key = WrappedKey::new(self.name.clone(), &key)?;
+        let payload = self.serialize_value(&value, Some(&info))?;
+        self.backend.write_update(&wrapped_key, Some(&payload))
+            .chain_err(|| "Failed to write cache update")?;
+        self.with_activity(|activity| activity.record_write());
+        let size = wrapped_key.serialized_key().len() + payload.len();
+        self.sync_value_update(key, value, info, size);
+        Ok(())
+    }
+
+    /// Removes the key from the map, and writes a tombstone to the backend so that the
+    /// key won't be restored on the next state load. Returns the removed value, if any.
+    pub fn remove(&self, key: &K) -> Result<Option<V>> {
+        let wrapped_key = WrappedKey::new(self.name.clone(), key)?;
+        self.backend.write_update(&wrapped_key, None)
+            .chain_err(|| "Failed to write cache delete")?;
+        self.with_activity(|activity| activity.record_write());
+        Ok(self.sync_value_delete(key))
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        match self.map.read() {
+            Ok(store) => store.get(key).map(|entry| entry.value.clone()),
+            Err(_) => panic!("Poison error"),
+        }
+    }
+
+    /// Returns when and by which instance the entry was last updated.
+    pub fn info(&self, key: &K) -> Option<EntryInfo> {
+        match self.map.read() {
+            Ok(store) => store.get(key).map(|entry| entry.info.clone()),
+            Err(_) => panic!("Poison error"),
+        }
+    }
+
+    /// Like `get`, but also returns when and by which instance the entry was last updated.
+    pub fn get_with_info(&self, key: &K) -> Option<(V, EntryInfo)> {
+        match self.map.read() {
+            Ok(store) => store.get(key).map(|entry| (entry.value.clone(), entry.info.clone())),
+            Err(_) => panic!("Poison error"),
+        }
+    }
+
+    /// Returns the update time of the oldest selected entry, if any entry has a known update
+    /// time.
+    pub fn oldest_update(&self, selection: &Selection) -> Option<DateTime<UTC>> {
+        match self.map.read() {
+            Ok(store) => store.select(selection)
+                .filter_map(|(_, entry)| entry.info.updated)
+                .min(),
+            Err(_) => panic!("Poison error"),
+        }
+    }
+
+    /// Removes from the local map the entries older than the TTL. Nothing is written to the
+    /// backend: every instance expires the entries on its own. Entries with an unknown update
+    /// time never expire.
+    pub fn expire(&self) -> usize {
+        let ttl = match self.ttl {
+            Some(ttl) => ttl,
+            None => return 0,
+        };
+        let expired = match self.map.read() {
+            Ok(store) => store.select(&Selection::All)
+                .filter(|&(_, entry)| entry.info.age().map(|age| age > ttl.as_secs() as i64).unwrap_or(false))
+                .map(|(k, _)| k.clone())
+                .collect::<Vec<K>>(),
+            Err(_) => panic!("Poison error"),
+        };
+        for key in &expired {
+            self.sync_value_delete(key);
+        }
+        expired.len()
+    }
+
+    /// If the map is over its memory limit, removes the least recently updated entries until
+    /// the map is 10% below the limit. Unlike expiry, eviction writes a tombstone for each
+    /// removed entry, so it should only run on one instance. Returns the number of evicted
+    /// entries.
+    pub fn evict(&self) -> Result<usize> {
+        let limit = match self.memory_limit {
+            Some(limit) => limit,
+            None => return Ok(0),
+        };
+        let (size, candidates) = match self.map.read() {
+            Ok(store) => {
+                if store.size() <= limit {
+                    return Ok(0);
+                }
+                let to_free = store.size() - limit / 10 * 9;
+                (store.size(), store.eviction_candidates(to_free, self.evict_by))
+            },
+            Err(_) => panic!("Poison error"),
+        };
+        for key in &candidates {
+            self.remove(key)?;
+            self.evicted.fetch_add(1, Ordering::Relaxed);
+        }
+        info!("Map {} over its memory limit ({} of {} bytes): {} entries evicted",
+              self.name, size, limit, candidates.len());
+        Ok(candidates.len())
+    }
+
+    pub fn stats(&self) -> MapStats {
+        let (entries, approx_size) = match self.map.read() {
+            Ok(store) => (store.len(), store.size()),
+            Err(_) => panic!("Poison error"),
+        };
+        let (write_rate, last_update, replayed) = self.with_activity(|activity| {
+            (activity.writes.rate(UTC::now().timestamp()), activity.last_update, activity.replayed)
+        });
+        MapStats {
+            name: self.name.clone(),
+            entries: entries,
+            approx_size: approx_size,
+            memory_limit: self.memory_limit,
+            evicted: self.evicted.load(Ordering::Relaxed),
+            write_rate: write_rate,
+            last_update: last_update,
+            replayed: replayed,
+        }
+    }
+
+    /// Returns the serialized content of the map.
+    pub fn dump(&self) -> Result<Vec<(WrappedKey, Vec<u8>)>> {
+        match self.map.read() {
+            Ok(store) => store.select(&Selection::All)
+                .map(|(k, entry)| {
+                    let wrapped_key = WrappedKey::new(self.name.clone(), k)?;
+                    let payload = self.serialize_value(&entry.value, Some(&entry.info))?;
+                    Ok((wrapped_key, payload))
+                })
+                .collect::<Result<Vec<_>>>(),
+            Err(_) => panic!("Poison error"),
+        }
+    }
+
+    /// Calls `f` with an iterator over all the entries of the map, while holding the read lock.
+    pub fn lock_iter<F, R>(&self, f: F) -> R
+            where F: Fn(MapIter<K, V>) -> R {
+        self.lock_select_iter(&Selection::All, f)
+    }
+
+    /// Calls `f` with an iterator over the selected entries, while holding the read lock.
+    pub fn lock_select_iter<F, R>(&self, selection: &Selection, f: F) -> R
+            where F: Fn(MapIter<K, V>) -> R {
+        match self.map.read() {
+            Ok(store) => f(Box::new(store.select(selection).map(|(k, entry)| (k, &entry.value)))),
+            Err(_) => panic!("Poison error"),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self.map.read() {
+            Ok(store) => store.len(),
+            Err(_) => panic!("Poison error"),
+        }
+    }
+
+    pub fn select_count(&self, selection: &Selection) -> usize {
+        self.lock_select_iter(selection, |iter| iter.count())
+    }
+
+    pub fn select_clone(&self, selection: &Selection) -> Vec<(K, V)> {
+        self.lock_select_iter(selection, |iter| {
+            iter.map(|(k, v)| (k.clone(), v.clone()))
+                .collect::<Vec<(K, V)>>()
+        })
+    }
+
+    pub fn select_clone_k(&self, selection: &Selection) -> Vec<K> {
+        self.lock_select_iter(selection, |iter| {
+            iter.map(|(k, _)| k.clone())
+                .collect::<Vec<K>>()
+        })
+    }
+
+    // The filter functions scan the whole map: when possible, use a selection instead.
+
+    pub fn count<F>(&self, f: F) -> usize
+            where F: Fn(&K) -> bool {
+        self.lock_iter(|iter| iter.filter(|&(k, _)| f(k)).count())
+    }
+
+    pub fn filter_clone<F>(&self, f: F) -> Vec<(K, V)>
+            where F: Fn(&K) -> bool {
+        self.lock_iter(|iter| {
+            iter.filter(|&(k, _)| f(k))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect::<Vec<(K, V)>>()
+        })
+    }
+
+    pub fn filter_clone_v<F>(&self, f: F) -> Vec<V>
+            where F: Fn(&K) -> bool {
+        self.lock_iter(|iter| {
+            iter.filter(|&(k, _)| f(k))
+                .map(|(_, v)| v.clone())
+                .collect::<Vec<V>>()
+        })
+    }
+
+    pub fn filter_clone_k<F>(&self, f: F) -> Vec<K>
+            where F: Fn(&K) -> bool {
+        self.lock_iter(|iter| {
+            iter.filter(|&(k, _)| f(k))
+                .map(|(k, _)| k.clone())
+                .collect::<Vec<K>>()
+        })
+    }
+}
+
+
+//
+// ********** CACHE **********
+//
+
+pub type MetricsCache = ReplicatedMap<(ClusterId, BrokerId), BrokerMetrics>;
+pub type OffsetsCache = ReplicatedMap<(ClusterId, String, TopicName), Vec<i64>>;
+pub type BrokerCache = ReplicatedMap<ClusterId, Vec<Broker>>;
+pub type TopicCache = ReplicatedMap<(ClusterId, TopicName), Vec<Partition>>;
+pub type GroupCache = ReplicatedMap<(ClusterId, String), Group>;
+pub type TopicConfigCache = ReplicatedMap<(ClusterId, TopicName), Vec<ConfigValue>>;
+pub type BrokerConfigCache = ReplicatedMap<(ClusterId, BrokerId), Vec<ConfigValue>>;
+pub type EventCache = ReplicatedMap<(ClusterId, u64), ClusterEvent>;
+
+/// Indexes of the offsets cache.
+pub const GROUP_INDEX: &'static str = "group";
+pub const TOPIC_INDEX: &'static str = "topic";
+
+fn offsets_group(key: &(ClusterId, String, TopicName)) -> &str {
+    &key.1
+}
+
+fn offsets_topic(key: &(ClusterId, String, TopicName)) -> &str {
+    &key.2
+}
+
+
+pub struct Cache {
+    pub metrics: MetricsCache,
+    pub offsets: OffsetsCache,
+    pub brokers: BrokerCache,
+    pub topics: TopicCache,
+    pub groups: GroupCache,
+    pub topic_configs: TopicConfigCache,
+    pub broker_configs: BrokerConfigCache,
+    pub events: EventCache,
+    backend: Arc<ReplicaBackend>,
+}
+
+impl Cache {
+    pub fn new(backend: Arc<ReplicaBackend>, config: &CachingConfig) -> Cache {
+        let ttl = |name: &str| config.ttl.get(name).map(|secs| Duration::from_secs(*secs));
+        // Unchanged values are rewritten often enough not to expire on the other instances
+        let rewrite_interval = |name: &str| {
+            let interval = ttl(name).map(|ttl| cmp::min(ttl / 2, Duration::from_secs(config.rewrite_interval)))
+                .unwrap_or(Duration::from_secs(config.rewrite_interval));
+            Some(interval)
+        };
+        let memory_limit = |name: &str| config.memory_limits.get(name).map(|mb| *mb as usize * 1024 * 1024);
+        Cache {
+            metrics: ReplicatedMap::new("metrics", backend.clone())
+                .with_ttl(ttl("metrics"))
+                .with_rewrite_interval(rewrite_interval("metrics"))
+                .with_memory_limit(memory_limit("metrics"), None),
+            // Offsets are evicted by group, starting from the least recently committed ones
+            offsets: ReplicatedMap::new("offsets", backend.clone())
+                .with_ttl(ttl("offsets"))
+                .with_rewrite_interval(rewrite_interval("offsets"))
+                .with_memory_limit(memory_limit("offsets"), Some(GROUP_INDEX))
+                .with_index(GROUP_INDEX, offsets_group)
+                .with_index(TOPIC_INDEX, offsets_topic),
+            brokers: ReplicatedMap::with_migrations("brokers", backend.clone(),
+                                                    Migrations::new()
+                                                        .value_upgrade(upgrade_brokers_v1)
+                                                        .value_upgrade(upgrade_brokers_v2))
+                .with_ttl(ttl("brokers"))
+                .with_rewrite_interval(rewrite_interval("brokers"))
+                .with_memory_limit(memory_limit("brokers"), None),
+            topics: ReplicatedMap::new("topics", backend.clone())
+                .with_ttl(ttl("topics"))
+                .with_rewrite_interval(rewrite_interval("topics"))
+                .with_memory_limit(memory_limit("topics"), None),
+            groups: ReplicatedMap::with_migrations("groups", backend.clone(),
+                                                   Migrations::new().value_upgrade(upgrade_group_v1))
+                .with_ttl(ttl("groups"))
+                .with_rewrite_interval(rewrite_interval("groups"))
+                .with_memory_limit(memory_limit("groups"), None),
+            topic_configs: ReplicatedMap::new("topic_configs", backend.clone())
+                .with_ttl(ttl("topic_configs"))
+                .with_rewrite_interval(rewrite_interval("topic_configs"))
+                .with_memory_limit(memory_limit("topic_configs"), None),
+            broker_configs: ReplicatedMap::new("broker_configs", backend.clone())
+                .with_ttl(ttl("broker_configs"))
+                .with_rewrite_interval(rewrite_interval("broker_configs"))
+                .with_memory_limit(memory_limit("broker_configs"), None),
+            events: ReplicatedMap::new("events", backend.clone())
+                .with_ttl(ttl("events"))
+                .with_rewrite_interval(rewrite_interval("events"))
+                .with_memory_limit(memory_limit("events"), None),
+            backend: backend,
+        }
+    }
+
+    /// Restores the content of all the maps from the backend. If a snapshot is available, only
+    /// the updates following the snapshot are read from the backend.
+    pub fn load_state(&self, snapshot_path: Option<&PathBuf>) -> Result<()> {
+        let positions = match snapshot_path {
+            Some(path) => self.load_snapshot(path)
+                .unwrap_or_else(|e| {
+                    format_error_chain!(e);
+                    None
+                }),
+            None => None,
+        };
+        self.backend.load_state(self, positions.as_ref())
+    }
+
+    /// After the state has been loaded, keeps applying the updates coming from other instances.
+    pub fn start_tailing(&self) -> Result<()> {
+        self.backend.start_tailing(Box::new(self.alias()))
+    }
+
+    /// Applies the snapshot to the cache, returning the positions it covers. A snapshot of a
+    /// different topic is ignored.
+    fn load_snapshot(&self, path: &PathBuf) -> Result<Option<ReplicaPositions>> {
+        let snapshot = match snapshot::read_snapshot(path)? {
+            Some(snapshot) => snapshot,
+            None => return Ok(None),
+        };
+        let current_topic = self.backend.positions().map(|p| p.topic);
+        if current_topic.as_ref() != Some(&snapshot.positions.topic) {
+            warn!("Ignoring snapshot of {}, the cache is using {:?}", snapshot.positions.topic, current_topic);
+            return Ok(None);
+        }
+        info!("Loading {} entries from snapshot {}", snapshot.entries.len(), path.display());
+        for &(ref w_key, ref payload) in &snapshot.entries {
+            let update = ReplicaCacheUpdate::Set { key: w_key, payload: payload };
+            if let Err(e) = self.receive_update(w_key.cache_name(), update) {
+                format_error_chain!(e);
+            }
+        }
+        Ok(Some(snapshot.positions))
+    }
+
+    /// Returns the serialized content of all the maps.
+    fn dump(&self) -> Result<Vec<(WrappedKey, Vec<u8>)>> {
+        let mut entries = Vec::new();
+        entries.extend(self.metrics.dump()?);
+        entries.extend(self.offsets.dump()?);
+        entries.extend(self.brokers.dump()?);
+        entries.extend(self.topics.dump()?);
+        entries.extend(self.groups.dump()?);
+        entries.extend(self.topic_configs.dump()?);
+        entries.extend(self.broker_configs.dump()?);
+        entries.extend(self.events.dump()?);
+        Ok(entries)
+    }
+
+    fn write_snapshot(&self, path: &PathBuf) -> Result<()> {
+        // The positions are read before the content: updates received in between will be read
+        // again from the backend when the snapshot is loaded.
+        let positions = match self.backend.positions() {
+            Some(positions) => positions,
+            None => bail!("The cache backend doesn't support snapshots"),
+        };
+        let snapshot = snapshot::Snapshot { positions: positions, entries: self.dump()? };
+        snapshot::write_snapshot(path, &snapshot)?;
+        debug!("Snapshot with {} entries written to {}", snapshot.entries.len(), path.display());
+        Ok(())
+    }
+
+    /// Periodically writes a snapshot of the cache to the specified path.
+    pub fn start_snapshots(&self, path: PathBuf, interval: Duration) -> Result<()> {
+        if self.backend.positions().is_none() {
+            warn!("The cache backend doesn't support snapshots, snapshots are disabled");
+            return Ok(());
+        }
+        let cache = self.alias();
+        thread::Builder::new()
+            .name("Cache snapshots".into())
+            .spawn(move || loop {
+                thread::sleep(interval);
+                if let Err(e) = cache.write_snapshot(&path) {
+                    format_error_chain!(e);
+                }
+            })
+            .chain_err(|| "Failed to start snapshot thread")?;
+        Ok(())
+    }
+
+    fn expire(&self) {
+        let expired = self.metrics.expire() + self.offsets.expire() + self.brokers.expire()
+            + self.topics.expire() + self.groups.expire() + self.topic_configs.expire()
+            + self.broker_configs.expire() + self.events.expire();
+        if expired > 0 {
+            debug!("{} expired cache entries removed", expired);
+        }
+    }
+
+    /// Periodically removes the expired entries from the maps that have a TTL.
+    pub fn start_expiry(&self, interval: Duration) -> Result<()> {
+        let cache = self.alias();
+        thread::Builder::new()
+            .name("Cache expiry".into())
+            .spawn(move || loop {
+                thread::sleep(interval);
+                cache.expire();
+            })
+            .chain_err(|| "Failed to start cache expiry thread")?;
+        Ok(())
+    }
+
+    fn evict(&self) {
+        let results = vec![self.metrics.evict(), self.offsets.evict(), self.brokers.evict(),
+                           self.topics.evict(), self.groups.evict(), self.topic_configs.evict(),
+                           self.broker_configs.evict(), self.events.evict()];
+        for result in results {
+            if let Err(e) = result {
+                format_error_chain!(e);
+            }
+        }
+    }
+
+    /// Periodically evicts entries from the maps exceeding their memory limit. Only the leader
+    /// evicts, since the evictions are replicated to the other instances.
+    pub fn start_eviction(&self, interval: Duration, leadership: Leadership) -> Result<()> {
+        let cache = self.alias();
+        thread::Builder::new()
+            .name("Cache eviction".into())
+            .spawn(move || loop {
+                thread::sleep(interval);
+                if leadership.is_leader() {
+                    cache.evict();
+                }
+            })
+            .chain_err(|| "Failed to start cache eviction thread")?;
+        Ok(())
+    }
+
+    pub fn map_stats(&self) -> Vec<MapStats> {
+        vec![self.metrics.stats(), self.offsets.stats(), self.brokers.stats(),
+             self.topics.stats(), self.groups.stats(), self.topic_configs.stats(),
+             self.broker_configs.stats(), self.events.stats()]
+    }
+
+    pub fn write_stats(&self) -> WriteStats {
+        self.backend.write_counters().stats()
+    }
+
+    pub fn replica_lag(&self) -> Option<i64> {
+        self.backend.replica_lag()
+    }
+
+    pub fn alias(&self) -> Cache {
+        Cache {
+            metrics: self.metrics.alias(),
+            offsets: self.offsets.alias(),
+            brokers: self.brokers.alias(),
+            topics: self.topics.alias(),
+            groups: self.groups.alias(),
+            topic_configs: self.topic_configs.alias(),
+            broker_configs: self.broker_configs.alias(),
+            events: self.events.alias(),
+            backend: self.backend.clone(),
+        }
+    }
+}
+
+impl UpdateReceiver for Cache {
+    fn receive_update(&self, cache_name: &str, update: ReplicaCacheUpdate) -> Result<()> {
+        match cache_name.as_ref() {
+            "metrics" => self.metrics.receive_update(update),
+            "offsets" => self.offsets.receive_update(update),
+            "brokers" => self.brokers.receive_update(update),
+            "topics" => self.topics.receive_update(update),
+            "groups" => self.groups.receive_update(update),
+            "topic_configs" => self.topic_configs.receive_update(update),
+            "broker_configs" => self.broker_configs.receive_update(update),
+            "events" => self.events.receive_update(update),
+            _ => bail!("Unknown cache name: {}", cache_name),
+        }
+    }
+}
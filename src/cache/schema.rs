@@ -0,0 +1,311 @@
+use serde::de::{self, Deserialize, Deserializer, SeqVisitor, Visitor};
+use serde::ser::{Serialize, SerializeTupleStruct, Serializer};
+use serde_cbor::{self, ObjectKey, Value};
+
+use error::*;
+
+use std::fmt;
+use std::result;
+
+
+/// Converts a serialized key or value from one schema version to the next one.
+pub type Upgrade = fn(&[u8]) -> Result<Vec<u8>>;
+
+fn unchanged(bytes: &[u8]) -> Result<Vec<u8>> {
+    Ok(bytes.to_vec())
+}
+
+/// The key of every record stored in the backend: the name of the map, the serialized key and,
+/// for the backends shared by multiple instances, the kafka-view instance that wrote the record.
+/// The source lets an instance recognize its own writes, tombstones included.
+///
+/// The encoding doesn't depend on the schema version, so that compaction replaces the records of
+/// an entry written by older versions of kafka-view. The version of the key is recorded in the
+/// value envelope. Keys without a source are encoded like the keys written before the source
+/// was recorded.
+#[derive(Debug, Hash, Eq, PartialEq, Clone)]
+pub struct WrappedKey {
+    cache_name: String,
+    serialized_key: Vec<u8>,
+    source: Option<String>,
+}
+
+impl WrappedKey {
+    pub fn new<K>(cache_name: String, key: &K) -> Result<WrappedKey>
+            where K: Serialize {
+        let serialized_key = serde_cbor::to_vec(key)
+            .chain_err(|| "Failed to serialize key")?;
+        Ok(WrappedKey { cache_name: cache_name, serialized_key: serialized_key, source: None })
+    }
+
+    pub fn cache_name(&self) -> &str {
+        &self.cache_name
+    }
+
+    pub fn serialized_key(&self) -> &[u8] {
+        &self.serialized_key
+    }
+
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_ref().map(|source| source.as_str())
+    }
+
+    /// The same key, as written by the specified instance.
+    pub fn with_source(&self, source: Option<&str>) -> WrappedKey {
+        WrappedKey {
+            cache_name: self.cache_name.clone(),
+            serialized_key: self.serialized_key.clone(),
+            source: source.map(|source| source.to_owned()),
+        }
+    }
+}
+
+impl Serialize for WrappedKey {
+    fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error>
+            where S: Serializer {
+        let mut tuple = serializer.serialize_tuple_struct("WrappedKey", if self.source.is_some() { 3 } else { 2 })?;
+        tuple.serialize_field(&self.cache_name)?;
+        tuple.serialize_field(&self.serialized_key)?;
+        if let Some(ref source) = self.source {
+            tuple.serialize_field(source)?;
+        }
+        tuple.end()
+    }
+}
+
+struct WrappedKeyVisitor;
+
+impl Visitor for WrappedKeyVisitor {
+    type Value = WrappedKey;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a cache name, a serialized key and an optional source")
+    }
+
+    fn visit_seq<V>(self, mut visitor: V) -> result::Result<WrappedKey, V::Error>
+            where V: SeqVisitor {
+        let cache_name = match visitor.visit::<String>()? {
+            Some(cache_name) => cache_name,
+            None => return Err(de::Error::invalid_length(0, &self)),
+        };
+        let serialized_key = match visitor.visit::<Vec<u8>>()? {
+            Some(serialized_key) => serialized_key,
+            None => return Err(de::Error::invalid_length(1, &self)),
+        };
+        let source = visitor.visit::<String>()?;
+        Ok(WrappedKey { cache_name: cache_name, serialized_key: serialized_key, source: source })
+    }
+}
+
+impl Deserialize for WrappedKey {
+    fn deserialize<D>(deserializer: D) -> result::Result<WrappedKey, D::Error>
+            where D: Deserializer {
+        deserializer.deserialize_seq(WrappedKeyVisitor)
+    }
+}
+
+fn first_version() -> u32 {
+    1
+}
+
+/// Wraps every value stored in the backend, recording the schema versions of the key and of the
+/// value at the time the update was written, when it was written (milliseconds since the epoch)
+/// and by which kafka-view instance.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ValueEnvelope {
+    pub version: u32,
+    pub payload: Vec<u8>,
+    #[serde(default)]
+    pub timestamp: Option<i64>,
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default = "first_version")]
+    pub key_version: u32,
+}
+
+impl ValueEnvelope {
+    /// Decodes the envelope. Values written before the envelope was introduced, recognized by
+    /// the missing version field, are returned as version 0. Envelopes that can't be decoded
+    /// are an error.
+    pub fn decode(bytes: &[u8]) -> Result<ValueEnvelope> {
+        let value = serde_cbor::from_slice::<Value>(bytes)
+            .chain_err(|| "Failed to parse value")?;
+        let has_version = match value {
+            Value::Object(ref fields) => fields.contains_key(&ObjectKey::String("version".to_owned())),
+            _ => false,
+        };
+        if !has_version {
+            return Ok(ValueEnvelope {
+                version: 0,
+                payload: bytes.to_vec(),
+                timestamp: None,
+                source: None,
+                key_version: first_version(),
+            });
+        }
+        serde_cbor::from_slice::<ValueEnvelope>(bytes)
+            .chain_err(|| "Failed to parse value envelope")
+    }
+}
+
+/// The schema history of the keys and values of a map. The upgrade at index `i` converts from
+/// version `i` to version `i + 1`, and the current version is the number of registered upgrades.
+/// Version 0 is the format used before the envelope was introduced, identical to version 1.
+///
+/// When a field is added to a type stored in the cache, register an upgrade for it so that the
+/// records written by older versions can still be loaded.
+#[derive(Clone)]
+pub struct Migrations {
+    key_upgrades: Vec<Upgrade>,
+    value_upgrades: Vec<Upgrade>,
+}
+
+impl Migrations {
+    pub fn new() -> Migrations {
+        Migrations {
+            key_upgrades: vec![unchanged as Upgrade],
+            value_upgrades: vec![unchanged as Upgrade],
+        }
+    }
+
+    pub fn key_upgrade(mut self, upgrade: Upgrade) -> Migrations {
+        self.key_upgrades.push(upgrade);
+        self
+    }
+
+    pub fn value_upgrade(mut self, upgrade: Upgrade) -> Migrations {
+        self.value_upgrades.push(upgrade);
+        self
+    }
+
+    pub fn key_version(&self) -> u32 {
+        self.key_upgrades.len() as u32
+    }
+
+    pub fn value_version(&self) -> u32 {
+        self.value_upgrades.len() as u32
+    }
+
+    pub fn upgrade_key(&self, bytes: &[u8], from: u32) -> Result<Vec<u8>> {
+        upgrade(&self.key_upgrades, bytes, from)
+            .chain_err(|| "Failed to upgrade key")
+    }
+
+    pub fn upgrade_value(&self, bytes: &[u8], from: u32) -> Result<Vec<u8>> {
+        upgrade(&self.value_upgrades, bytes, from)
+            .chain_err(|| "Failed to upgrade value")
+    }
+
+    /// Parses the key of a tombstone. Tombstones have no envelope recording the version of the
+    /// key: the key is parsed with the current schema first, then upgraded from the previous
+    /// versions, starting from the most recent one.
+    pub fn parse_tombstone_key<K: Deserialize>(&self, bytes: &[u8]) -> Result<K> {
+        if let Ok(key) = serde_cbor::from_slice::<K>(bytes) {
+            return Ok(key);
+        }
+        for version in (1..self.key_version()).rev() {
+            let key = self.upgrade_key(bytes, version).ok()
+                .and_then(|upgraded| serde_cbor::from_slice::<K>(&upgraded).ok());
+            if let Some(key) = key {
+                return Ok(key);
+            }
+        }
+        bail!("Failed to parse key")
+    }
+
+    /// Wraps the serialized value in an envelope with the current versions.
+    pub fn wrap_value(&self, payload: Vec<u8>, timestamp: i64, source: &str) -> Result<Vec<u8>> {
+        let envelope = ValueEnvelope {
+            version: self.value_version(),
+            payload: payload,
+            timestamp: Some(timestamp),
+            source: Some(source.to_owned()),
+            key_version: self.key_version(),
+        };
+        serde_cbor::to_vec(&envelope).chain_err(|| "Failed to serialize envelope")
+    }
+}
+
+fn upgrade(upgrades: &[Upgrade], bytes: &[u8], from: u32) -> Result<Vec<u8>> {
+    if from as usize > upgrades.len() {
+        bail!("Version {} was written by a newer version of kafka-view (current: {})", from, upgrades.len());
+    }
+    let mut bytes = bytes.to_vec();
+    for (version, upgrade) in upgrades.iter().enumerate().skip(from as usize) {
+        bytes = upgrade(&bytes).chain_err(|| format!("Upgrade from version {} failed", version))?;
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_cbor;
+
+    #[derive(Serialize, Deserialize)]
+    struct OldValue { a: i32 }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct NewValue { a: i32, b: String }
+
+    fn add_b(bytes: &[u8]) -> Result<Vec<u8>> {
+        let old = serde_cbor::from_slice::<OldValue>(bytes).chain_err(|| "Parse failed")?;
+        serde_cbor::to_vec(&NewValue { a: old.a, b: "default".to_owned() }).chain_err(|| "Write failed")
+    }
+
+    #[test]
+    fn legacy_and_old_values_are_upgraded() {
+        let migrations = Migrations::new().value_upgrade(add_b);
+        let expected = NewValue { a: 42, b: "default".to_owned() };
+        let old_bytes = serde_cbor::to_vec(&OldValue { a: 42 }).unwrap();
+
+        // Written before the envelope existed
+        let legacy = ValueEnvelope::decode(&old_bytes).unwrap();
+        assert_eq!(legacy.version, 0);
+        let upgraded = migrations.upgrade_value(&legacy.payload, legacy.version).unwrap();
+        assert_eq!(serde_cbor::from_slice::<NewValue>(&upgraded).unwrap(), expected);
+
+        // Written with version 1
+        let v1 = Migrations::new().wrap_value(old_bytes, 0, "test").unwrap();
+        let envelope = ValueEnvelope::decode(&v1).unwrap();
+        let upgraded = migrations.upgrade_value(&envelope.payload, envelope.version).unwrap();
+        assert_eq!(serde_cbor::from_slice::<NewValue>(&upgraded).unwrap(), expected);
+
+        // Written by a newer version
+        assert!(migrations.upgrade_value(&upgraded, 3).is_err());
+
+        // A corrupt envelope is not mistaken for a legacy value
+        let mut corrupt = v1.clone();
+        let last = corrupt.len() - 1;
+        corrupt.truncate(last);
+        assert!(ValueEnvelope::decode(&corrupt).is_err());
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct NewKey(String, i32);
+
+    fn add_partition(bytes: &[u8]) -> Result<Vec<u8>> {
+        let old = serde_cbor::from_slice::<String>(bytes).chain_err(|| "Parse failed")?;
+        serde_cbor::to_vec(&NewKey(old, 0)).chain_err(|| "Write failed")
+    }
+
+    #[test]
+    fn wrapped_key_encoding_is_independent_of_the_version() {
+        let legacy = serde_cbor::to_vec(&("topics".to_owned(), serde_cbor::to_vec(&"key").unwrap())).unwrap();
+        let key = WrappedKey::new("topics".to_owned(), &"key").unwrap();
+        assert_eq!(serde_cbor::to_vec(&key).unwrap(), legacy);
+        assert_eq!(serde_cbor::from_slice::<WrappedKey>(&legacy).unwrap(), key);
+
+        let own_key = key.with_source(Some("instance-1"));
+        let decoded = serde_cbor::from_slice::<WrappedKey>(&serde_cbor::to_vec(&own_key).unwrap()).unwrap();
+        assert_eq!(decoded.source(), Some("instance-1"));
+        assert_eq!(decoded.with_source(None), key);
+
+        // Tombstone keys are upgraded from the version they were written with
+        let migrations = Migrations::new().key_upgrade(add_partition);
+        let old_key = serde_cbor::to_vec(&"topic").unwrap();
+        let new_key = serde_cbor::to_vec(&NewKey("topic".to_owned(), 3)).unwrap();
+        assert_eq!(migrations.parse_tombstone_key::<NewKey>(&old_key).unwrap(), NewKey("topic".to_owned(), 0));
+        assert_eq!(migrations.parse_tombstone_key::<NewKey>(&new_key).unwrap(), NewKey("topic".to_owned(), 3));
+    }
+}
@@ -0,0 +1,45 @@
+use serde_cbor;
+
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+use cache::{ReplicaPositions, WrappedKey};
+use error::*;
+
+
+/// The content of all the maps of the cache, together with the positions in the backend
+/// that the content covers.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Snapshot {
+    pub positions: ReplicaPositions,
+    pub entries: Vec<(WrappedKey, Vec<u8>)>,
+}
+
+/// Reads the snapshot from disk. Returns `None` if the snapshot doesn't exist.
+pub fn read_snapshot(path: &Path) -> Result<Option<Snapshot>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let file = File::open(path)
+        .chain_err(|| format!("Failed to open {}", path.display()))?;
+    let snapshot = serde_cbor::from_reader(BufReader::new(file))
+        .chain_err(|| format!("Failed to parse snapshot {}", path.display()))?;
+    Ok(Some(snapshot))
+}
+
+/// Writes the snapshot to a temporary file first, so that a crash during the write doesn't
+/// corrupt the previous snapshot.
+pub fn write_snapshot(path: &Path, snapshot: &Snapshot) -> Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    {
+        let file = File::create(&tmp_path)
+            .chain_err(|| format!("Failed to create {}", tmp_path.display()))?;
+        let mut writer = BufWriter::new(file);
+        serde_cbor::ser::to_writer(&mut writer, snapshot)
+            .chain_err(|| "Failed to serialize snapshot")?;
+        writer.flush().chain_err(|| "Failed to write snapshot")?;
+    }
+    fs::rename(&tmp_path, path)
+        .chain_err(|| format!("Failed to replace {}", path.display()))
+}
@@ -0,0 +1,304 @@
+use chrono::{DateTime, UTC};
+
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::iter;
+
+use cache::EntryInfo;
+use metadata::ClusterId;
+
+
+/// Keys of the replicated maps. Every key belongs to a cluster, and the content of the maps is
+/// partitioned by cluster.
+pub trait ClusterKey {
+    fn cluster_id(&self) -> &ClusterId;
+}
+
+impl ClusterKey for ClusterId {
+    fn cluster_id(&self) -> &ClusterId {
+        self
+    }
+}
+
+impl<A> ClusterKey for (ClusterId, A) {
+    fn cluster_id(&self) -> &ClusterId {
+        &self.0
+    }
+}
+
+impl<A, B> ClusterKey for (ClusterId, A, B) {
+    fn cluster_id(&self) -> &ClusterId {
+        &self.0
+    }
+}
+
+/// Extracts from a key the value it is indexed by, such as the group or topic name.
+pub type IndexFn<K> = fn(&K) -> &str;
+
+/// Selects a subset of the entries of a map. Only the selected entries are visited, so the cost
+/// of a lookup is proportional to the size of the result, except for `All`.
+pub enum Selection<'a> {
+    All,
+    Cluster(&'a ClusterId),
+    /// The entries of the cluster having the specified value in the named index.
+    Index(&'a ClusterId, &'static str, &'a str),
+}
+
+pub struct Entry<V> {
+    pub value: V,
+    pub info: EntryInfo,
+    /// Approximate memory used by the entry, in bytes.
+    pub size: usize,
+}
+
+pub type EntryIter<'a, K, V> = Box<Iterator<Item=(&'a K, &'a Entry<V>)> + 'a>;
+
+struct Partition<K, V> {
+    entries: HashMap<K, Entry<V>>,
+    // One map for each index of the store, from indexed value to keys
+    indexes: Vec<HashMap<String, HashSet<K>>>,
+}
+
+impl<K: Eq + Hash, V> Partition<K, V> {
+    fn new(index_count: usize) -> Partition<K, V> {
+        Partition {
+            entries: HashMap::new(),
+            indexes: (0..index_count).map(|_| HashMap::new()).collect(),
+        }
+    }
+}
+
+/// The content of a replicated map, partitioned by cluster and with secondary indexes.
+pub struct Store<K, V> {
+    index_fns: Vec<(&'static str, IndexFn<K>)>,
+    partitions: HashMap<ClusterId, Partition<K, V>>,
+    len: usize,
+    size: usize,
+}
+
+impl<K, V> Store<K, V> where K: Eq + Hash + Clone + ClusterKey {
+    pub fn new() -> Store<K, V> {
+        Store {
+            index_fns: Vec::new(),
+            partitions: HashMap::new(),
+            len: 0,
+            size: 0,
+        }
+    }
+
+    /// Adds a secondary index. Indexes can only be added to an empty store.
+    pub fn add_index(&mut self, name: &'static str, index_fn: IndexFn<K>) {
+        assert!(self.len == 0, "Indexes must be added before inserting entries");
+        self.index_fns.push((name, index_fn));
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Approximate memory used by all the entries, in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn clusters(&self) -> Vec<ClusterId> {
+        self.partitions.keys().cloned().collect()
+    }
+
+    pub fn get(&self, key: &K) -> Option<&Entry<V>> {
+        self.partitions.get(key.cluster_id())
+            .and_then(|partition| partition.entries.get(key))
+    }
+
+    /// Inserts the entry, returning the previous value.
+    pub fn insert(&mut self, key: K, value: V, info: EntryInfo, size: usize) -> Option<V> {
+        let index_count = self.index_fns.len();
+        let partition = self.partitions.entry(key.cluster_id().clone())
+            .or_insert_with(|| Partition::new(index_count));
+        if !partition.entries.contains_key(&key) {
+            // Indexed values are derived from the key, so they only change for new keys
+            for (i, &(_, ref index_fn)) in self.index_fns.iter().enumerate() {
+                partition.indexes[i].entry(index_fn(&key).to_owned())
+                    .or_insert_with(HashSet::new)
+                    .insert(key.clone());
+            }
+            self.len += 1;
+        }
+        let previous = partition.entries.insert(key, Entry { value: value, info: info, size: size });
+        self.size += size;
+        if let Some(ref entry) = previous {
+            self.size -= entry.size;
+        }
+        previous.map(|entry| entry.value)
+    }
+
+    /// Removes the entry, returning its value.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let (removed, partition_empty) = match self.partitions.get_mut(key.cluster_id()) {
+            Some(partition) => {
+                let removed = partition.entries.remove(key);
+                if removed.is_some() {
+                    for (i, &(_, ref index_fn)) in self.index_fns.iter().enumerate() {
+                        let indexed_value = index_fn(key);
+                        let keys_empty = match partition.indexes[i].get_mut(indexed_value) {
+                            Some(keys) => {
+                                keys.remove(key);
+                                keys.is_empty()
+                            },
+                            None => false,
+                        };
+                        if keys_empty {
+                            partition.indexes[i].remove(indexed_value);
+                        }
+                    }
+                }
+                (removed, partition.entries.is_empty())
+            },
+            None => return None,
+        };
+        if partition_empty {
+            self.partitions.remove(key.cluster_id());
+        }
+        removed.map(|entry| {
+            self.len -= 1;
+            self.size -= entry.size;
+            entry.value
+        })
+    }
+
+    /// Returns the least recently updated entries, enough to free the specified amount of
+    /// memory. If an index is specified, all the entries sharing the same indexed value are
+    /// evicted together, ordered by their most recent update. Entries with an unknown update
+    /// time are evicted first.
+    pub fn eviction_candidates(&self, bytes_to_free: usize, index_name: Option<&str>) -> Vec<K> {
+        // Groups of keys to evict together, with their last update and size
+        let mut groups: Vec<(Option<DateTime<UTC>>, usize, Vec<&K>)> = Vec::new();
+        match index_name {
+            Some(index_name) => {
+                let position = self.index_position(index_name);
+                for partition in self.partitions.values() {
+                    for keys in partition.indexes[position].values() {
+                        let entries = keys.iter().map(|key| &partition.entries[key]).collect::<Vec<_>>();
+                        let last_update = entries.iter().map(|entry| entry.info.updated).max().and_then(|u| u);
+                        let size = entries.iter().map(|entry| entry.size).sum();
+                        groups.push((last_update, size, keys.iter().collect()));
+                    }
+                }
+            },
+            None => {
+                for partition in self.partitions.values() {
+                    for (key, entry) in &partition.entries {
+                        groups.push((entry.info.updated, entry.size, vec![key]));
+                    }
+                }
+            },
+        };
+        groups.sort_by_key(|&(last_update, _, _)| last_update);
+
+        let mut freed = 0;
+        let mut candidates = Vec::new();
+        for (_, size, keys) in groups {
+            if freed >= bytes_to_free {
+                break;
+            }
+            freed += size;
+            candidates.extend(keys.into_iter().cloned());
+        }
+        candidates
+    }
+
+    fn index_position(&self, index_name: &str) -> usize {
+        match self.index_fns.iter().position(|&(name, _)| name == index_name) {
+            Some(position) => position,
+            None => panic!("Unknown index: {}", index_name),
+        }
+    }
+
+    /// Iterates over the selected entries.
+    pub fn select<'a>(&'a self, selection: &Selection) -> EntryIter<'a, K, V> {
+        match *selection {
+            Selection::All => Box::new(self.partitions.values().flat_map(|p| p.entries.iter())),
+            Selection::Cluster(cluster_id) => match self.partitions.get(cluster_id) {
+                Some(partition) => Box::new(partition.entries.iter()),
+                None => Box::new(iter::empty()),
+            },
+            Selection::Index(cluster_id, index_name, indexed_value) => {
+                let position = self.index_position(index_name);
+                let partition = match self.partitions.get(cluster_id) {
+                    Some(partition) => partition,
+                    None => return Box::new(iter::empty()),
+                };
+                match partition.indexes[position].get(indexed_value) {
+                    Some(keys) => Box::new(keys.iter().map(move |key| (key, &partition.entries[key]))),
+                    None => Box::new(iter::empty()),
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group_index(key: &(ClusterId, String, String)) -> &str {
+        &key.1
+    }
+
+    fn info() -> EntryInfo {
+        EntryInfo { updated: None, source: None }
+    }
+
+    fn selected_keys(store: &Store<(ClusterId, String, String), i32>, selection: &Selection) -> Vec<String> {
+        let mut keys = store.select(selection).map(|(k, _)| k.2.clone()).collect::<Vec<_>>();
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn partitions_and_indexes_follow_updates() {
+        let mut store = Store::new();
+        store.add_index("group", group_index);
+        let c1 = ClusterId::from("c1");
+        let c2 = ClusterId::from("c2");
+        store.insert((c1.clone(), "g1".to_owned(), "t1".to_owned()), 1, info(), 10);
+        store.insert((c1.clone(), "g1".to_owned(), "t2".to_owned()), 2, info(), 10);
+        store.insert((c1.clone(), "g2".to_owned(), "t3".to_owned()), 3, info(), 10);
+        store.insert((c2.clone(), "g1".to_owned(), "t4".to_owned()), 4, info(), 10);
+        assert_eq!(store.insert((c2.clone(), "g1".to_owned(), "t4".to_owned()), 5, info(), 20), Some(4));
+        assert_eq!(store.len(), 4);
+        assert_eq!(store.size(), 50);
+
+        assert_eq!(selected_keys(&store, &Selection::Cluster(&c1)), vec!["t1", "t2", "t3"]);
+        assert_eq!(selected_keys(&store, &Selection::Index(&c1, "group", "g1")), vec!["t1", "t2"]);
+        assert_eq!(selected_keys(&store, &Selection::Index(&c2, "group", "g2")), Vec::<String>::new());
+
+        assert_eq!(store.remove(&(c1.clone(), "g1".to_owned(), "t1".to_owned())), Some(1));
+        assert_eq!(selected_keys(&store, &Selection::Index(&c1, "group", "g1")), vec!["t2"]);
+        store.remove(&(c2.clone(), "g1".to_owned(), "t4".to_owned()));
+        assert_eq!(store.clusters(), vec![c1.clone()]);
+        assert_eq!(selected_keys(&store, &Selection::All), vec!["t2", "t3"]);
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.size(), 20);
+    }
+
+    #[test]
+    fn eviction_prefers_least_recently_updated_groups() {
+        use chrono::TimeZone;
+
+        let mut store = Store::new();
+        store.add_index("group", group_index);
+        let c1 = ClusterId::from("c1");
+        let at = |secs| EntryInfo { updated: Some(UTC.timestamp(secs, 0)), source: None };
+        store.insert((c1.clone(), "old".to_owned(), "t1".to_owned()), 1, at(100), 10);
+        store.insert((c1.clone(), "old".to_owned(), "t2".to_owned()), 2, at(200), 10);
+        store.insert((c1.clone(), "new".to_owned(), "t3".to_owned()), 3, at(150), 10);
+        store.insert((c1.clone(), "new".to_owned(), "t4".to_owned()), 4, at(300), 10);
+
+        let mut by_group = store.eviction_candidates(5, Some("group")).into_iter().map(|k| k.2).collect::<Vec<_>>();
+        by_group.sort();
+        assert_eq!(by_group, vec!["t1", "t2"]);
+        let by_entry = store.eviction_candidates(15, None).into_iter().map(|k| k.2).collect::<Vec<_>>();
+        assert_eq!(by_entry, vec!["t1", "t3"]);
+    }
+}
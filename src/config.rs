@@ -12,6 +12,27 @@ use std::fs::File;
 pub struct ClusterConfig {
     pub broker_list: Vec<String>,
     pub zookeeper: String,
+    // Override the global refresh periods (in seconds) for this cluster
+    pub metadata_refresh: Option<u64>,
+    pub metrics_refresh: Option<u64>,
+    // Timeouts of the metadata and group list requests, in seconds
+    #[serde(default = "default_metadata_timeout")]
+    pub metadata_timeout: u64,
+    #[serde(default = "default_group_list_timeout")]
+    pub group_list_timeout: u64,
+    // Timeout of the requests sent to each broker to probe its API versions, in seconds
+    #[serde(default = "default_probe_timeout")]
+    pub probe_timeout: u64,
+    // Protocol used to connect to the brokers. The brokers are only probed over plaintext.
+    #[serde(default = "default_security_protocol")]
+    pub security_protocol: String,
+    // What is collected from the cluster
+    #[serde(default = "default_true")]
+    pub fetch_groups: bool,
+    #[serde(default = "default_true")]
+    pub consume_offsets: bool,
+    #[serde(default = "default_true")]
+    pub fetch_metrics: bool,
 }
 
 impl ClusterConfig {
@@ -20,10 +41,117 @@ impl ClusterConfig {
     }
 }
 
+fn default_metadata_timeout() -> u64 {
+    60
+}
+
+fn default_group_list_timeout() -> u64 {
+    30
+}
+
+fn default_probe_timeout() -> u64 {
+    5
+}
+
+fn default_security_protocol() -> String {
+    "plaintext".to_owned()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum CachingBackend {
+    #[serde(rename = "kafka")]
+    Kafka,
+    #[serde(rename = "file")]
+    File,
+}
+
+fn default_caching_backend() -> CachingBackend {
+    CachingBackend::Kafka
+}
+
+fn default_compaction_interval() -> u64 {
+    600
+}
+
+fn default_snapshot_interval() -> u64 {
+    300
+}
+
+fn default_expiry_interval() -> u64 {
+    60
+}
+
+fn default_rewrite_interval() -> u64 {
+    3600
+}
+
+fn default_eviction_interval() -> u64 {
+    60
+}
+
+fn default_event_log_size() -> usize {
+    1000
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_topic_partitions() -> i32 {
+    1
+}
+
+fn default_topic_replication() -> i32 {
+    3
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CachingConfig {
-    pub cluster: ClusterId,
-    pub topic: String,
+    #[serde(default = "default_caching_backend")]
+    pub backend: CachingBackend,
+    // Identifies this kafka-view instance in the shared cache, random if not specified
+    pub instance_id: Option<String>,
+    // Kafka backend
+    pub cluster: Option<ClusterId>,
+    pub topic: Option<String>,
+    // The topic is created if missing, and must be compacted
+    #[serde(default = "default_true")]
+    pub create_topic: bool,
+    #[serde(default = "default_topic_partitions")]
+    pub topic_partitions: i32,
+    #[serde(default = "default_topic_replication")]
+    pub topic_replication: i32,
+    #[serde(default = "default_true")]
+    pub strict_topic_check: bool,
+    // Encrypts the cache records using the keys in the file (kafka backend)
+    pub encryption_key_file: Option<String>,
+    // The key used to encrypt the message keys, never rotated (required with encryption_key_file)
+    pub key_encryption_key_file: Option<String>,
+    // Only one instance polls the clusters, elected using a topic next to the replica topic
+    #[serde(default)]
+    pub leader_election: bool,
+    // Local snapshots of the cache, to speed up restarts
+    pub snapshot_path: Option<String>,
+    #[serde(default = "default_snapshot_interval")]
+    pub snapshot_interval: u64,
+    // File backend
+    pub path: Option<String>,
+    #[serde(default = "default_compaction_interval")]
+    pub compaction_interval: u64,
+    // Entries not updated for longer than the TTL (in seconds, by map name) are removed
+    #[serde(default)]
+    pub ttl: HashMap<String, u64>,
+    #[serde(default = "default_expiry_interval")]
+    pub expiry_interval: u64,
+    // Unchanged values are not written again to the cache, unless older than this (in seconds)
+    #[serde(default = "default_rewrite_interval")]
+    pub rewrite_interval: u64,
+    // Approximate memory budget (in MB, by map name): the least recently updated entries are
+    // evicted when it's exceeded
+    #[serde(default)]
+    pub memory_limits: HashMap<String, u64>,
+    #[serde(default = "default_eviction_interval")]
+    pub eviction_interval: u64,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -31,6 +159,9 @@ pub struct Config {
     pub metadata_refresh: u64,
     pub metrics_refresh: u64,
     pub consumer_offsets_group_id: String,
+    // Number of metadata change events kept for each cluster
+    #[serde(default = "default_event_log_size")]
+    pub event_log_size: usize,
     pub clusters: HashMap<ClusterId, ClusterConfig>,
     pub caching: CachingConfig,
 }
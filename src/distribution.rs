@@ -0,0 +1,217 @@
+use cache::{Cache, Selection};
+use metadata::{Broker, BrokerId, ClusterId, Partition, TopicName};
+
+use std::collections::{BTreeSet, HashMap};
+
+
+/// Leaders and replicas hosted by a broker.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct BrokerLoad {
+    pub broker_id: BrokerId,
+    pub leaders: usize,
+    /// Partitions led by the broker for which it's also the preferred (first) replica.
+    pub preferred_leaders: usize,
+    pub replicas: usize,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TopicSkew {
+    pub topic: TopicName,
+    pub partitions: usize,
+    pub non_preferred_leaders: usize,
+    /// How much the broker with most replicas of the topic exceeds an even distribution: 0 if
+    /// the replicas are balanced, 1 if it has twice its share, etc.
+    pub skew: f64,
+}
+
+/// How the partitions of a cluster are spread across its brokers.
+#[derive(Serialize, Debug, Clone)]
+pub struct ClusterDistribution {
+    pub partitions: usize,
+    /// Partitions whose leader is not the preferred replica. Offline partitions are not counted.
+    pub non_preferred_leaders: usize,
+    pub non_preferred_leader_percentage: f64,
+    pub brokers: Vec<BrokerLoad>,
+    pub topics: Vec<TopicSkew>,
+}
+
+/// Number of replicas and leaders of every topic on every broker, for heatmaps: `replicas[t][b]`
+/// is the number of replicas of `topics[t]` hosted by `brokers[b]`.
+#[derive(Serialize, Debug, Clone)]
+pub struct ReplicaMatrix {
+    pub brokers: Vec<BrokerId>,
+    pub topics: Vec<TopicName>,
+    pub replicas: Vec<Vec<usize>>,
+    pub leaders: Vec<Vec<usize>>,
+}
+
+fn is_non_preferred_leader(partition: &Partition) -> bool {
+    partition.leader != -1 && partition.replicas.first().map(|&id| id != partition.leader).unwrap_or(false)
+}
+
+/// All the brokers of the cluster, including the ones hosting replicas but missing from the
+/// metadata.
+fn broker_ids(brokers: &[Broker], topics: &[(TopicName, Vec<Partition>)]) -> Vec<BrokerId> {
+    let mut ids = brokers.iter().map(|broker| broker.id).collect::<BTreeSet<_>>();
+    for &(_, ref partitions) in topics {
+        for p in partitions {
+            ids.extend(p.replicas.iter().cloned());
+        }
+    }
+    ids.into_iter().collect()
+}
+
+fn topic_skew(topic: &str, partitions: &[Partition], broker_count: usize) -> TopicSkew {
+    let mut replicas_by_broker = HashMap::new();
+    for p in partitions {
+        for replica in &p.replicas {
+            *replicas_by_broker.entry(*replica).or_insert(0) += 1;
+        }
+    }
+    let total = replicas_by_broker.values().sum::<usize>();
+    let max = replicas_by_broker.values().cloned().max().unwrap_or(0);
+    let skew = if total == 0 || broker_count == 0 {
+        0.0
+    } else {
+        let even_share = (total + broker_count - 1) / broker_count;
+        (max as f64 - even_share as f64) / even_share as f64
+    };
+    TopicSkew {
+        topic: topic.to_owned(),
+        partitions: partitions.len(),
+        non_preferred_leaders: partitions.iter().filter(|p| is_non_preferred_leader(p)).count(),
+        skew: skew,
+    }
+}
+
+impl ClusterDistribution {
+    pub fn compute(brokers: &[Broker], topics: &[(TopicName, Vec<Partition>)]) -> ClusterDistribution {
+        let ids = broker_ids(brokers, topics);
+        let mut loads = ids.iter()
+            .map(|&id| (id, BrokerLoad { broker_id: id, leaders: 0, preferred_leaders: 0, replicas: 0 }))
+            .collect::<HashMap<_, _>>();
+        let mut partition_count = 0;
+        let mut non_preferred = 0;
+        for &(_, ref partitions) in topics {
+            for p in partitions {
+                partition_count += 1;
+                if is_non_preferred_leader(p) {
+                    non_preferred += 1;
+                }
+                if let Some(load) = loads.get_mut(&p.leader) {
+                    load.leaders += 1;
+                    if !is_non_preferred_leader(p) {
+                        load.preferred_leaders += 1;
+                    }
+                }
+                for replica in &p.replicas {
+                    if let Some(load) = loads.get_mut(replica) {
+                        load.replicas += 1;
+                    }
+                }
+            }
+        }
+        let mut topic_skews = topics.iter()
+            .map(|&(ref topic, ref partitions)| topic_skew(topic, partitions, ids.len()))
+            .collect::<Vec<_>>();
+        topic_skews.sort_by(|a, b| a.topic.cmp(&b.topic));
+        let percentage = if partition_count == 0 { 0.0 } else { non_preferred as f64 * 100.0 / partition_count as f64 };
+        ClusterDistribution {
+            partitions: partition_count,
+            non_preferred_leaders: non_preferred,
+            non_preferred_leader_percentage: percentage,
+            brokers: ids.iter().filter_map(|id| loads.remove(id)).collect(),
+            topics: topic_skews,
+        }
+    }
+
+    /// Computes the distribution from the content of the cache. Returns `None` if the cluster is
+    /// unknown.
+    pub fn from_cache(cache: &Cache, cluster_id: &ClusterId) -> Option<ClusterDistribution> {
+        let brokers = match cache.brokers.get(cluster_id) {
+            Some(brokers) => brokers,
+            None => return None,
+        };
+        Some(ClusterDistribution::compute(&brokers, &cluster_topics(cache, cluster_id)))
+    }
+}
+
+impl ReplicaMatrix {
+    pub fn compute(brokers: &[Broker], topics: &[(TopicName, Vec<Partition>)]) -> ReplicaMatrix {
+        let ids = broker_ids(brokers, topics);
+        let columns = ids.iter().enumerate().map(|(i, &id)| (id, i)).collect::<HashMap<_, _>>();
+        let mut matrix = ReplicaMatrix {
+            brokers: ids.clone(),
+            topics: Vec::with_capacity(topics.len()),
+            replicas: Vec::with_capacity(topics.len()),
+            leaders: Vec::with_capacity(topics.len()),
+        };
+        for &(ref topic, ref partitions) in topics {
+            let mut replicas = vec![0; ids.len()];
+            let mut leaders = vec![0; ids.len()];
+            for p in partitions {
+                for replica in &p.replicas {
+                    replicas[columns[replica]] += 1;
+                }
+                if let Some(&column) = columns.get(&p.leader) {
+                    leaders[column] += 1;
+                }
+            }
+            matrix.topics.push(topic.clone());
+            matrix.replicas.push(replicas);
+            matrix.leaders.push(leaders);
+        }
+        matrix
+    }
+
+    pub fn from_cache(cache: &Cache, cluster_id: &ClusterId) -> Option<ReplicaMatrix> {
+        let brokers = match cache.brokers.get(cluster_id) {
+            Some(brokers) => brokers,
+            None => return None,
+        };
+        Some(ReplicaMatrix::compute(&brokers, &cluster_topics(cache, cluster_id)))
+    }
+}
+
+/// The topics of the cluster, sorted by name.
+fn cluster_topics(cache: &Cache, cluster_id: &ClusterId) -> Vec<(TopicName, Vec<Partition>)> {
+    let mut topics = cache.topics.select_clone(&Selection::Cluster(cluster_id))
+        .into_iter()
+        .map(|((_, topic_name), partitions)| (topic_name, partitions))
+        .collect::<Vec<_>>();
+    topics.sort_by(|a, b| a.0.cmp(&b.0));
+    topics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use metadata::test_fixtures::{broker, partition};
+
+    #[test]
+    fn distribution_counts_leaders_and_replicas() {
+        let brokers = vec![broker(1, None), broker(2, None)];
+        let topics = vec![
+            ("balanced".to_owned(), vec![partition(0, 1, vec![1, 2], vec![1, 2]),
+                                         partition(1, 2, vec![2, 1], vec![2, 1])]),
+            ("skewed".to_owned(), vec![partition(0, 1, vec![2], vec![2]), partition(1, 2, vec![2], vec![2])]),
+        ];
+        let distribution = ClusterDistribution::compute(&brokers, &topics);
+
+        assert_eq!(distribution.partitions, 4);
+        assert_eq!(distribution.non_preferred_leaders, 1);
+        assert_eq!(distribution.non_preferred_leader_percentage, 25.0);
+        assert_eq!(distribution.brokers, vec![
+            BrokerLoad { broker_id: 1, leaders: 2, preferred_leaders: 1, replicas: 2 },
+            BrokerLoad { broker_id: 2, leaders: 2, preferred_leaders: 2, replicas: 4 },
+        ]);
+        assert_eq!(distribution.topics[0].skew, 0.0);
+        assert_eq!(distribution.topics[1].skew, 1.0);
+        assert_eq!(distribution.topics[1].non_preferred_leaders, 1);
+
+        let matrix = ReplicaMatrix::compute(&brokers, &topics);
+        assert_eq!(matrix.brokers, vec![1, 2]);
+        assert_eq!(matrix.replicas, vec![vec![2, 2], vec![0, 2]]);
+        assert_eq!(matrix.leaders, vec![vec![1, 1], vec![1, 1]]);
+    }
+}
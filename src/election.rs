@@ -0,0 +1,180 @@
+use rdkafka::client::Context;
+use rdkafka::config::{ClientConfig, TopicConfig};
+use rdkafka::consumer::{BaseConsumer, Consumer, ConsumerContext, EmptyConsumerContext, Rebalance};
+use rdkafka::statistics::Statistics;
+
+use admin::{create_admin, create_topic};
+use config::CachingConfig;
+use error::*;
+
+use std::cmp;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+
+/// Tells whether this kafka-view instance should poll the clusters. Only the leader runs the
+/// fetchers, the other instances serve the web UI using the replicated cache.
+#[derive(Clone)]
+pub struct Leadership {
+    is_leader: Arc<AtomicBool>,
+}
+
+impl Leadership {
+    /// Used when the election is disabled: the instance is always the leader.
+    pub fn always_leader() -> Leadership {
+        Leadership { is_leader: Arc::new(AtomicBool::new(true)) }
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.is_leader.load(Ordering::Relaxed)
+    }
+
+    /// Blocks until this instance becomes the leader.
+    pub fn wait_leadership(&self) {
+        while !self.is_leader() {
+            thread::sleep(Duration::from_secs(1));
+        }
+    }
+}
+
+/// After this time without a heartbeat, the group coordinator considers the instance dead
+/// and reassigns its partitions.
+const SESSION_TIMEOUT_MS: u64 = 10000;
+
+/// The election topic is empty, so fetch requests only return after this time. It's longer than
+/// the session timeout, so that fetch responses alone don't keep an instance in charge when it
+/// can only reach the partition leader.
+const FETCH_WAIT_MAX_MS: u64 = 30000;
+
+/// The name of the topic used for the election of the instances sharing the replica topic.
+pub fn election_topic_name(replica_topic_name: &str) -> String {
+    format!("{}_election", replica_topic_name)
+}
+
+/// Creates the election topic if it doesn't exist. The topic has a single partition, and no
+/// message is ever written to it: the election consumers don't read any data.
+pub fn ensure_election_topic(brokers: &str, topic_name: &str, config: &CachingConfig) -> Result<()> {
+    let consumer = ClientConfig::new()
+        .set("bootstrap.servers", brokers)
+        .create::<BaseConsumer<EmptyConsumerContext>>()
+        .chain_err(|| "Consumer creation failed")?;
+    let metadata = consumer.fetch_metadata(30000)
+        .chain_err(|| "Failed to fetch metadata")?;
+    if metadata.topics().iter().any(|t| t.name() == topic_name && t.error().is_none()) {
+        return Ok(());
+    }
+    if !config.create_topic {
+        bail!("The election topic {} doesn't exist and create_topic is disabled", topic_name);
+    }
+    let replication_factor = cmp::min(config.topic_replication, metadata.brokers().len() as i32);
+    info!("Creating election topic {} (replication factor: {})", topic_name, replication_factor);
+    let admin = create_admin(brokers)?;
+    create_topic(&admin, topic_name, 1, replication_factor, &[], Duration::from_secs(30))
+        .chain_err(|| format!("Failed to create election topic {}", topic_name))
+}
+
+/// What the election consumer knows about its membership in the group.
+struct ElectionState {
+    owns_partition: bool,
+    // The last time the cluster answered a request of the consumer
+    last_contact: Instant,
+    responses: i64,
+}
+
+/// Follows the assignment of the election partition through the rebalance callbacks, and the
+/// requests answered by the cluster through the statistics.
+struct ElectionContext {
+    topic_name: String,
+    state: Arc<Mutex<ElectionState>>,
+}
+
+impl Context for ElectionContext {
+    fn stats(&self, statistics: Statistics) {
+        let responses = statistics.brokers.values().map(|broker| broker.rx).sum::<i64>();
+        if let Ok(mut state) = self.state.lock() {
+            if responses > state.responses {
+                state.last_contact = Instant::now();
+            }
+            state.responses = responses;
+        }
+    }
+}
+
+impl ConsumerContext for ElectionContext {
+    fn post_rebalance(&self, rebalance: &Rebalance) {
+        if let Ok(mut state) = self.state.lock() {
+            state.owns_partition = match *rebalance {
+                Rebalance::Assign(ref assignment) => assignment.elements().iter()
+                    .any(|elem| elem.topic() == self.topic_name && elem.partition() == 0),
+                Rebalance::Revoke | Rebalance::Error(_) => false,
+            };
+            state.last_contact = Instant::now();
+        }
+    }
+}
+
+type ElectionConsumer = BaseConsumer<ElectionContext>;
+
+/// Starts the leader election among all the instances using the same election topic. Every
+/// instance joins the same consumer group on the topic, and the instance that gets its only
+/// partition assigned is the leader. When the leader goes away, the group is rebalanced and
+/// another instance takes over.
+pub fn start_election(brokers: &str, topic_name: &str, group_id: &str) -> Result<Leadership> {
+    let state = Arc::new(Mutex::new(ElectionState {
+        owns_partition: false,
+        last_contact: Instant::now(),
+        responses: 0,
+    }));
+    let context = ElectionContext { topic_name: topic_name.to_owned(), state: state.clone() };
+    let mut consumer: ElectionConsumer = ClientConfig::new()
+        .set("group.id", group_id)
+        .set("bootstrap.servers", brokers)
+        .set("session.timeout.ms", &SESSION_TIMEOUT_MS.to_string())
+        .set("enable.auto.commit", "false")
+        .set("enable.partition.eof", "false")
+        .set("fetch.wait.max.ms", &FETCH_WAIT_MAX_MS.to_string())
+        .set("statistics.interval.ms", "1000")
+        .set_default_topic_config(
+            TopicConfig::new()
+            .set("auto.offset.reset", "largest")
+            .finalize())
+        .create_with_context(context)
+        .chain_err(|| "Election consumer creation failed")?;
+
+    consumer.subscribe(&vec![topic_name])
+        .chain_err(|| "Can't subscribe to the election topic")?;
+
+    let leadership = Leadership { is_leader: Arc::new(AtomicBool::new(false)) };
+    let leadership_clone = leadership.clone();
+    thread::Builder::new()
+        .name("Leader election".into())
+        .spawn(move || election_loop(consumer, &state, leadership_clone))
+        .chain_err(|| "Failed to start leader election thread")?;
+
+    Ok(leadership)
+}
+
+fn election_loop(consumer: ElectionConsumer, state: &Mutex<ElectionState>, leadership: Leadership) {
+    let session_timeout = Duration::from_millis(SESSION_TIMEOUT_MS);
+    loop {
+        // Polling serves the callbacks, the topic is empty.
+        if let Err(e) = consumer.poll(500) {
+            warn!("Error while polling election consumer: {}", e);
+        }
+        // The assignment is stale if the instance can't reach the cluster: the group coordinator
+        // might already have assigned the partition to another instance.
+        let is_leader = match state.lock() {
+            Ok(state) => state.owns_partition && state.last_contact.elapsed() <= session_timeout,
+            Err(_) => false,
+        };
+        if leadership.is_leader.swap(is_leader, Ordering::Relaxed) != is_leader {
+            if is_leader {
+                info!("This instance is now the leader, starting to poll the clusters");
+            } else {
+                info!("This instance is not the leader anymore, stopping polling the clusters");
+            }
+        }
+    }
+}
@@ -0,0 +1,259 @@
+use chrono::{DateTime, UTC};
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use cache::{EventCache, Selection};
+use error::*;
+use metadata::{Broker, BrokerId, ClusterId, Group, Partition, TopicName};
+
+
+/// A change detected by comparing two consecutive snapshots of the metadata of a cluster.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum MetadataEvent {
+    TopicCreated { topic: TopicName, partitions: usize },
+    TopicDeleted { topic: TopicName },
+    PartitionCountChanged { topic: TopicName, old: usize, new: usize },
+    LeaderMoved { topic: TopicName, partition: i32, old: BrokerId, new: BrokerId },
+    IsrShrank { topic: TopicName, partition: i32, removed: Vec<BrokerId> },
+    IsrExpanded { topic: TopicName, partition: i32, added: Vec<BrokerId> },
+    BrokerJoined { broker_id: BrokerId, hostname: String },
+    BrokerLeft { broker_id: BrokerId, hostname: String },
+    GroupStateChanged { group: String, old: String, new: String },
+    GroupRemoved { group: String },
+}
+
+impl MetadataEvent {
+    /// The topic the event refers to, if any.
+    pub fn topic(&self) -> Option<&str> {
+        match *self {
+            MetadataEvent::TopicCreated { ref topic, .. } => Some(topic),
+            MetadataEvent::TopicDeleted { ref topic } => Some(topic),
+            MetadataEvent::PartitionCountChanged { ref topic, .. } => Some(topic),
+            MetadataEvent::LeaderMoved { ref topic, .. } => Some(topic),
+            MetadataEvent::IsrShrank { ref topic, .. } => Some(topic),
+            MetadataEvent::IsrExpanded { ref topic, .. } => Some(topic),
+            MetadataEvent::BrokerJoined { .. } => None,
+            MetadataEvent::BrokerLeft { .. } => None,
+            MetadataEvent::GroupStateChanged { .. } => None,
+            MetadataEvent::GroupRemoved { .. } => None,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            MetadataEvent::TopicCreated { .. } => "Topic created",
+            MetadataEvent::TopicDeleted { .. } => "Topic deleted",
+            MetadataEvent::PartitionCountChanged { .. } => "Partition count changed",
+            MetadataEvent::LeaderMoved { .. } => "Leader moved",
+            MetadataEvent::IsrShrank { .. } => "ISR shrank",
+            MetadataEvent::IsrExpanded { .. } => "ISR expanded",
+            MetadataEvent::BrokerJoined { .. } => "Broker joined",
+            MetadataEvent::BrokerLeft { .. } => "Broker left",
+            MetadataEvent::GroupStateChanged { .. } => "Group state changed",
+            MetadataEvent::GroupRemoved { .. } => "Group removed",
+        }
+    }
+}
+
+fn format_ids(ids: &[BrokerId]) -> String {
+    ids.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+impl fmt::Display for MetadataEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MetadataEvent::TopicCreated { ref topic, partitions } =>
+                write!(f, "Topic {} created with {} partitions", topic, partitions),
+            MetadataEvent::TopicDeleted { ref topic } =>
+                write!(f, "Topic {} deleted", topic),
+            MetadataEvent::PartitionCountChanged { ref topic, old, new } =>
+                write!(f, "Topic {} went from {} to {} partitions", topic, old, new),
+            MetadataEvent::LeaderMoved { ref topic, partition, old, new } =>
+                write!(f, "Leader of {}/{} moved from {} to {}", topic, partition, old, new),
+            MetadataEvent::IsrShrank { ref topic, partition, ref removed } =>
+                write!(f, "Brokers {} left the ISR of {}/{}", format_ids(removed), topic, partition),
+            MetadataEvent::IsrExpanded { ref topic, partition, ref added } =>
+                write!(f, "Brokers {} joined the ISR of {}/{}", format_ids(added), topic, partition),
+            MetadataEvent::BrokerJoined { broker_id, ref hostname } =>
+                write!(f, "Broker {} ({}) joined the cluster", broker_id, hostname),
+            MetadataEvent::BrokerLeft { broker_id, ref hostname } =>
+                write!(f, "Broker {} ({}) left the cluster", broker_id, hostname),
+            MetadataEvent::GroupStateChanged { ref group, ref old, ref new } =>
+                write!(f, "Group {} went from {} to {}", group, old, new),
+            MetadataEvent::GroupRemoved { ref group } =>
+                write!(f, "Group {} is not registered anymore", group),
+        }
+    }
+}
+
+/// An event of the timeline, with the time it was detected.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ClusterEvent {
+    pub timestamp: DateTime<UTC>,
+    pub event: MetadataEvent,
+}
+
+//
+// ********** DIFFS **********
+//
+
+pub fn diff_brokers(old: &[Broker], new: &[Broker]) -> Vec<MetadataEvent> {
+    let mut events = Vec::new();
+    for broker in new {
+        if !old.iter().any(|b| b.id == broker.id) {
+            events.push(MetadataEvent::BrokerJoined { broker_id: broker.id, hostname: broker.hostname.clone() });
+        }
+    }
+    for broker in old {
+        if !new.iter().any(|b| b.id == broker.id) {
+            events.push(MetadataEvent::BrokerLeft { broker_id: broker.id, hostname: broker.hostname.clone() });
+        }
+    }
+    events
+}
+
+/// Compares two versions of the partitions of a topic. A missing version means the topic didn't
+/// exist.
+pub fn diff_topic(topic: &str, old: Option<&[Partition]>, new: Option<&[Partition]>) -> Vec<MetadataEvent> {
+    let (old, new) = match (old, new) {
+        (None, None) => return Vec::new(),
+        (None, Some(new)) => return vec![MetadataEvent::TopicCreated { topic: topic.to_owned(), partitions: new.len() }],
+        (Some(_), None) => return vec![MetadataEvent::TopicDeleted { topic: topic.to_owned() }],
+        (Some(old), Some(new)) => (old, new),
+    };
+    let mut events = Vec::new();
+    if old.len() != new.len() {
+        events.push(MetadataEvent::PartitionCountChanged { topic: topic.to_owned(), old: old.len(), new: new.len() });
+    }
+    let old_partitions = old.iter().map(|p| (p.id, p)).collect::<HashMap<_, _>>();
+    for new_p in new {
+        let old_p = match old_partitions.get(&new_p.id) {
+            Some(old_p) => old_p,
+            None => continue,
+        };
+        if old_p.leader != new_p.leader {
+            events.push(MetadataEvent::LeaderMoved {
+                topic: topic.to_owned(), partition: new_p.id, old: old_p.leader, new: new_p.leader
+            });
+        }
+        let old_isr = old_p.isr.iter().cloned().collect::<BTreeSet<_>>();
+        let new_isr = new_p.isr.iter().cloned().collect::<BTreeSet<_>>();
+        let removed = old_isr.difference(&new_isr).cloned().collect::<Vec<_>>();
+        let added = new_isr.difference(&old_isr).cloned().collect::<Vec<_>>();
+        if !removed.is_empty() {
+            events.push(MetadataEvent::IsrShrank { topic: topic.to_owned(), partition: new_p.id, removed: removed });
+        }
+        if !added.is_empty() {
+            events.push(MetadataEvent::IsrExpanded { topic: topic.to_owned(), partition: new_p.id, added: added });
+        }
+    }
+    events
+}
+
+pub fn diff_group(old: Option<&Group>, new: &Group) -> Vec<MetadataEvent> {
+    match old {
+        Some(old) if old.state != new.state => vec![MetadataEvent::GroupStateChanged {
+            group: new.name.clone(), old: old.state.clone(), new: new.state.clone()
+        }],
+        _ => Vec::new(),
+    }
+}
+
+//
+// ********** EVENT LOG **********
+//
+
+/// The timeline of the events of every cluster, stored in a replicated map. Only the most
+/// recent events of each cluster are kept.
+pub struct EventLog {
+    cache: EventCache,
+    capacity: usize,
+    // The id of the next event of each cluster. Ids are never reused, even after the events
+    // expire from the cache.
+    next_ids: Arc<Mutex<HashMap<ClusterId, u64>>>,
+}
+
+impl EventLog {
+    pub fn new(cache: EventCache, capacity: usize) -> EventLog {
+        EventLog { cache: cache, capacity: capacity, next_ids: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    pub fn alias(&self) -> EventLog {
+        EventLog { cache: self.cache.alias(), capacity: self.capacity, next_ids: self.next_ids.clone() }
+    }
+
+    /// Appends the events to the timeline of the cluster, removing the oldest events if the
+    /// timeline is full.
+    pub fn append(&self, cluster_id: &ClusterId, events: Vec<MetadataEvent>) -> Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        let mut ids = self.cache.select_clone_k(&Selection::Cluster(cluster_id))
+            .into_iter()
+            .map(|(_, id)| id)
+            .collect::<Vec<_>>();
+        ids.sort();
+        let mut next_ids = self.next_ids.lock()
+            .map_err(|_| ErrorKind::PoisonError("event ids".to_owned()))?;
+        // The counter starts after the events in the cache, which could have been written by
+        // another instance
+        let stored_next_id = ids.last().map(|id| id + 1).unwrap_or(0);
+        let next_id = next_ids.entry(cluster_id.clone()).or_insert(stored_next_id);
+        if *next_id < stored_next_id {
+            *next_id = stored_next_id;
+        }
+        let timestamp = UTC::now();
+        for event in events {
+            debug!("{}: {}", cluster_id, event);
+            self.cache.insert((cluster_id.clone(), *next_id), ClusterEvent { timestamp: timestamp, event: event })
+                .chain_err(|| "Failed to insert event in cache")?;
+            ids.push(*next_id);
+            *next_id += 1;
+        }
+        if ids.len() > self.capacity {
+            for &id in &ids[..ids.len() - self.capacity] {
+                self.cache.remove(&(cluster_id.clone(), id))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the events of the cluster, most recent first. If a topic is specified, only its
+/// events are returned.
+pub fn cluster_events(cache: &EventCache, cluster_id: &ClusterId, topic: Option<&str>) -> Vec<ClusterEvent> {
+    let mut events = cache.lock_select_iter(&Selection::Cluster(cluster_id), |iter| {
+        iter.filter(|&(_, e)| topic.is_none() || e.event.topic() == topic)
+            .map(|(&(_, id), e)| (id, e.clone()))
+            .collect::<Vec<_>>()
+    });
+    events.sort_by(|a, b| b.0.cmp(&a.0));
+    events.into_iter().map(|(_, e)| e).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use metadata::test_fixtures::partition;
+
+    #[test]
+    fn topic_diff_detects_partition_changes() {
+        let old = vec![partition(0, 1, vec![1, 2, 3], vec![1, 2, 3]), partition(1, 2, vec![1, 2, 3], vec![2, 3])];
+        let new = vec![partition(0, 2, vec![1, 2, 3], vec![2, 3]), partition(1, 2, vec![1, 2, 3], vec![1, 2, 3]),
+                       partition(2, 3, vec![1, 2, 3], vec![3])];
+
+        assert_eq!(diff_topic("t", Some(&old[..]), Some(&new[..])), vec![
+            MetadataEvent::PartitionCountChanged { topic: "t".to_owned(), old: 2, new: 3 },
+            MetadataEvent::LeaderMoved { topic: "t".to_owned(), partition: 0, old: 1, new: 2 },
+            MetadataEvent::IsrShrank { topic: "t".to_owned(), partition: 0, removed: vec![1] },
+            MetadataEvent::IsrExpanded { topic: "t".to_owned(), partition: 1, added: vec![1] },
+        ]);
+        assert_eq!(diff_topic("t", None, Some(&new[..])),
+                   vec![MetadataEvent::TopicCreated { topic: "t".to_owned(), partitions: 3 }]);
+        assert_eq!(diff_topic("t", Some(&old[..]), None),
+                   vec![MetadataEvent::TopicDeleted { topic: "t".to_owned() }]);
+        assert!(diff_topic("t", Some(&new[..]), Some(&new[..])).is_empty());
+    }
+}
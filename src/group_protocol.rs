@@ -0,0 +1,101 @@
+use byteorder::{BigEndian, ReadBytesExt};
+
+use error::*;
+use metadata::TopicName;
+
+use std::io::{Cursor, Read};
+
+
+/// Protocol type used by the Kafka consumers. Groups using other protocols (e.g. Kafka Connect)
+/// encode their metadata differently, and are not decoded.
+pub const CONSUMER_PROTOCOL: &'static str = "consumer";
+
+pub fn read_str(rdr: &mut Cursor<&[u8]>) -> Result<String> {
+    let strlen = rdr.read_i16::<BigEndian>().chain_err(|| "Failed to parse string len")?;
+    if strlen < 0 {
+        bail!("Unexpected null string");
+    }
+    let mut bytes = vec![0; strlen as usize];
+    rdr.read_exact(&mut bytes).chain_err(|| "String is truncated")?;
+    String::from_utf8(bytes).chain_err(|| "String is not valid UTF-8")
+}
+
+pub fn read_array_len(rdr: &mut Cursor<&[u8]>) -> Result<usize> {
+    let len = rdr.read_i32::<BigEndian>().chain_err(|| "Failed to parse array len")?;
+    // Every element takes at least one byte: anything longer than the rest is corrupted
+    let remaining = rdr.get_ref().len() as u64 - rdr.position();
+    if len < 0 || len as u64 > remaining {
+        bail!("Invalid array len: {}", len);
+    }
+    Ok(len as usize)
+}
+
+/// Decodes the topics a member subscribed to. Newer versions of the protocol append fields after
+/// the user data, which are ignored.
+pub fn decode_subscription(bytes: &[u8]) -> Result<Vec<TopicName>> {
+    let mut rdr = Cursor::new(bytes);
+    let _version = rdr.read_i16::<BigEndian>().chain_err(|| "Failed to parse subscription version")?;
+    let topic_count = read_array_len(&mut rdr)?;
+    let mut topics = Vec::with_capacity(topic_count);
+    for _ in 0..topic_count {
+        topics.push(read_str(&mut rdr).chain_err(|| "Failed to parse subscribed topic")?);
+    }
+    Ok(topics)
+}
+
+/// Decodes the partitions assigned to a member, by topic.
+pub fn decode_assignment(bytes: &[u8]) -> Result<Vec<(TopicName, Vec<i32>)>> {
+    // Members that haven't received an assignment yet have no assignment data
+    if bytes.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut rdr = Cursor::new(bytes);
+    let _version = rdr.read_i16::<BigEndian>().chain_err(|| "Failed to parse assignment version")?;
+    let topic_count = read_array_len(&mut rdr)?;
+    let mut assignment = Vec::with_capacity(topic_count);
+    for _ in 0..topic_count {
+        let topic = read_str(&mut rdr).chain_err(|| "Failed to parse assigned topic")?;
+        let partition_count = read_array_len(&mut rdr)?;
+        let mut partitions = Vec::with_capacity(partition_count);
+        for _ in 0..partition_count {
+            partitions.push(rdr.read_i32::<BigEndian>().chain_err(|| "Failed to parse assigned partition")?);
+        }
+        assignment.push((topic, partitions));
+    }
+    Ok(assignment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::WriteBytesExt;
+
+    fn write_str(buffer: &mut Vec<u8>, s: &str) {
+        buffer.write_i16::<BigEndian>(s.len() as i16).unwrap();
+        buffer.extend_from_slice(s.as_bytes());
+    }
+
+    #[test]
+    fn consumer_protocol_is_decoded() {
+        let mut subscription = Vec::new();
+        subscription.write_i16::<BigEndian>(0).unwrap();
+        subscription.write_i32::<BigEndian>(2).unwrap();
+        write_str(&mut subscription, "orders");
+        write_str(&mut subscription, "payments");
+        subscription.write_i32::<BigEndian>(-1).unwrap();  // no user data
+        assert_eq!(decode_subscription(&subscription).unwrap(), vec!["orders".to_owned(), "payments".to_owned()]);
+
+        let mut assignment = Vec::new();
+        assignment.write_i16::<BigEndian>(0).unwrap();
+        assignment.write_i32::<BigEndian>(1).unwrap();
+        write_str(&mut assignment, "orders");
+        assignment.write_i32::<BigEndian>(2).unwrap();
+        assignment.write_i32::<BigEndian>(0).unwrap();
+        assignment.write_i32::<BigEndian>(3).unwrap();
+        assignment.write_i32::<BigEndian>(-1).unwrap();
+        assert_eq!(decode_assignment(&assignment).unwrap(), vec![("orders".to_owned(), vec![0, 3])]);
+
+        assert!(decode_assignment(&[]).unwrap().is_empty());
+        assert!(decode_assignment(&assignment[..10]).is_err());
+    }
+}
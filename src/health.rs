@@ -0,0 +1,151 @@
+use cache::{Cache, Selection};
+use metadata::{Broker, BrokerId, ClusterId, Partition, TopicName};
+
+use std::collections::BTreeSet;
+
+
+/// Used when neither the topic nor the broker configuration are available.
+const DEFAULT_MIN_ISR: usize = 1;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PartitionRef {
+    pub topic: TopicName,
+    pub partition: i32,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum HealthStatus {
+    #[serde(rename = "ok")]
+    Ok,
+    #[serde(rename = "warning")]
+    Warning,
+    #[serde(rename = "critical")]
+    Critical,
+}
+
+/// Problems found in the metadata of a cluster.
+#[derive(Serialize, Debug, Clone)]
+pub struct ClusterHealth {
+    pub status: HealthStatus,
+    /// Partitions with some replica out of the ISR.
+    pub under_replicated: Vec<PartitionRef>,
+    /// Partitions without a leader.
+    pub offline: Vec<PartitionRef>,
+    /// Partitions whose ISR is smaller than `min.insync.replicas`: producers using `acks=all`
+    /// can't write to them.
+    pub below_min_isr: Vec<PartitionRef>,
+    /// Brokers hosting replicas, but missing from the metadata.
+    pub missing_brokers: Vec<BrokerId>,
+}
+
+impl ClusterHealth {
+    /// Computes the health of a cluster from its brokers and topics. `min_isr` returns the
+    /// `min.insync.replicas` setting of a topic, if known.
+    pub fn compute<F>(brokers: &[Broker], topics: &[(TopicName, Vec<Partition>)], min_isr: F) -> ClusterHealth
+            where F: Fn(&str) -> Option<usize> {
+        let mut health = ClusterHealth {
+            status: HealthStatus::Ok,
+            under_replicated: Vec::new(),
+            offline: Vec::new(),
+            below_min_isr: Vec::new(),
+            missing_brokers: Vec::new(),
+        };
+        let mut missing_brokers = BTreeSet::new();
+        for &(ref topic_name, ref partitions) in topics {
+            let topic_min_isr = min_isr(topic_name).unwrap_or(DEFAULT_MIN_ISR);
+            for p in partitions {
+                let partition_ref = || PartitionRef { topic: topic_name.clone(), partition: p.id };
+                if p.leader == -1 {
+                    health.offline.push(partition_ref());
+                }
+                if p.isr.len() < p.replicas.len() {
+                    health.under_replicated.push(partition_ref());
+                }
+                if p.isr.len() < topic_min_isr {
+                    health.below_min_isr.push(partition_ref());
+                }
+                for replica in &p.replicas {
+                    if !brokers.iter().any(|broker| broker.id == *replica) {
+                        missing_brokers.insert(*replica);
+                    }
+                }
+            }
+        }
+        health.missing_brokers = missing_brokers.into_iter().collect();
+        health.status = if !health.offline.is_empty() || !health.below_min_isr.is_empty() {
+            HealthStatus::Critical
+        } else if !health.under_replicated.is_empty() || !health.missing_brokers.is_empty() {
+            HealthStatus::Warning
+        } else {
+            HealthStatus::Ok
+        };
+        health
+    }
+
+    /// Computes the health of a cluster from the content of the cache. Returns `None` if the
+    /// cluster is unknown.
+    pub fn from_cache(cache: &Cache, cluster_id: &ClusterId) -> Option<ClusterHealth> {
+        let brokers = match cache.brokers.get(cluster_id) {
+            Some(brokers) => brokers,
+            None => return None,
+        };
+        let topics = cache.topics.select_clone(&Selection::Cluster(cluster_id))
+            .into_iter()
+            .map(|((_, topic_name), partitions)| (topic_name, partitions))
+            .collect::<Vec<_>>();
+        let broker_default = broker_min_isr(cache, cluster_id, &brokers);
+        let health = ClusterHealth::compute(&brokers, &topics, |topic_name| {
+            cache.topic_configs.get(&(cluster_id.clone(), topic_name.to_owned()))
+                .and_then(|config| {
+                    config.into_iter()
+                        .find(|value| value.name == "min.insync.replicas")
+                        .and_then(|value| value.value)
+                        .and_then(|value| value.parse::<usize>().ok())
+                })
+                .or(broker_default)
+        });
+        Some(health)
+    }
+}
+
+/// The `min.insync.replicas` setting of the first broker whose configuration is known.
+fn broker_min_isr(cache: &Cache, cluster_id: &ClusterId, brokers: &[Broker]) -> Option<usize> {
+    brokers.iter()
+        .filter_map(|broker| cache.broker_configs.get(&(cluster_id.clone(), broker.id)))
+        .filter_map(|config| {
+            config.into_iter()
+                .find(|value| value.name == "min.insync.replicas")
+                .and_then(|value| value.value)
+                .and_then(|value| value.parse::<usize>().ok())
+        })
+        .next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use metadata::test_fixtures::{broker, partition};
+
+    #[test]
+    fn health_aggregates_partition_problems() {
+        let brokers = vec![broker(1, None), broker(2, None)];
+        let topics = vec![
+            ("healthy".to_owned(), vec![partition(0, 1, vec![1, 2], vec![1, 2])]),
+            ("degraded".to_owned(), vec![partition(0, 1, vec![1, 2], vec![1]),
+                                         partition(1, 2, vec![2, 3], vec![2, 3])]),
+            ("offline".to_owned(), vec![partition(0, -1, vec![1], vec![])]),
+        ];
+        let health = ClusterHealth::compute(&brokers, &topics, |topic| if topic == "degraded" { Some(2) } else { None });
+
+        let degraded_0 = PartitionRef { topic: "degraded".to_owned(), partition: 0 };
+        let offline_0 = PartitionRef { topic: "offline".to_owned(), partition: 0 };
+        assert_eq!(health.offline, vec![offline_0.clone()]);
+        assert_eq!(health.under_replicated, vec![degraded_0.clone(), offline_0.clone()]);
+        assert_eq!(health.below_min_isr, vec![degraded_0, offline_0]);
+        assert_eq!(health.missing_brokers, vec![3]);
+        assert_eq!(health.status, HealthStatus::Critical);
+
+        let healthy = ClusterHealth::compute(&brokers, &topics[..1], |_| None);
+        assert_eq!(healthy.status, HealthStatus::Ok);
+    }
+}
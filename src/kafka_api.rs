@@ -0,0 +1,423 @@
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use admin::ConfigValue;
+use error::*;
+use group_protocol::{read_array_len, read_str};
+use metadata::{Broker, BrokerId};
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Cursor, Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{ATOMIC_USIZE_INIT, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+
+const CLIENT_ID: &'static str = "kafka-view";
+
+const METADATA_KEY: i16 = 3;
+const API_VERSIONS_KEY: i16 = 18;
+const CREATE_TOPICS_KEY: i16 = 19;
+const DESCRIBE_CONFIGS_KEY: i16 = 32;
+
+const TOPIC_RESOURCE_TYPE: i8 = 2;
+const BROKER_RESOURCE_TYPE: i8 = 4;
+
+/// A connection thread keeps running after a timeout, until the connection attempt fails.
+const MAX_CONNECTION_THREADS: usize = 16;
+static CONNECTION_THREADS: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Decrements the connection thread count when the thread terminates.
+struct ConnectionThreadGuard;
+
+impl Drop for ConnectionThreadGuard {
+    fn drop(&mut self) {
+        CONNECTION_THREADS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Kafka releases, from the most recent, identified by an API they introduced: the release is
+/// recognized if the broker supports the API (key) up to at least the specified version.
+const RELEASES: &'static [(&'static str, i16, i16)] = &[
+    ("2.8", 60, 0),     // DescribeCluster
+    ("2.7", 57, 0),     // UpdateFeatures
+    ("2.6", 48, 0),     // DescribeClientQuotas
+    ("2.4", 45, 0),     // AlterPartitionReassignments
+    ("2.3", 44, 0),     // IncrementalAlterConfigs
+    ("2.2", 43, 0),     // ElectLeaders
+    ("2.1", 1, 10),     // Fetch v10
+    ("2.0", 1, 8),      // Fetch v8
+    ("1.1", 42, 0),     // DeleteGroups
+    ("1.0", 37, 0),     // CreatePartitions
+    ("0.11.0", 22, 0),  // InitProducerId
+    ("0.10.2", 9, 2),   // OffsetFetch v2
+    ("0.10.1", 19, 0),  // CreateTopics
+    ("0.10.0", 18, 0),  // ApiVersions
+];
+
+/// The range of versions of an API supported by a broker.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ApiVersion {
+    pub api_key: i16,
+    pub min_version: i16,
+    pub max_version: i16,
+}
+
+/// Whether the broker can describe the configuration of topics and brokers (Kafka 0.11 and later).
+pub fn supports_describe_configs(api_versions: &[ApiVersion]) -> bool {
+    api_versions.iter().any(|version| version.api_key == DESCRIBE_CONFIGS_KEY)
+}
+
+/// The most recent release whose APIs are supported: the broker runs this release or a later
+/// one, e.g. "2.4" for a broker running 2.4 or 2.5.
+pub fn approximate_release(api_versions: &[ApiVersion]) -> Option<&'static str> {
+    RELEASES.iter()
+        .find(|&&(_, api_key, version)| {
+            api_versions.iter().any(|v| v.api_key == api_key && v.max_version >= version)
+        })
+        .map(|&(release, _, _)| release)
+}
+
+/// The parts of the cluster metadata that are not exposed by librdkafka.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClusterMetadata {
+    pub controller_id: BrokerId,
+    /// The host and port of each broker.
+    pub hosts: HashMap<BrokerId, (String, i32)>,
+    /// The rack of each broker with a configured rack.
+    pub racks: HashMap<BrokerId, String>,
+}
+
+/// A resource whose configuration can be described.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConfigResource<'a> {
+    Topic(&'a str),
+    Broker(BrokerId),
+}
+
+impl<'a> ConfigResource<'a> {
+    fn resource_type(&self) -> i8 {
+        match *self {
+            ConfigResource::Topic(_) => TOPIC_RESOURCE_TYPE,
+            ConfigResource::Broker(_) => BROKER_RESOURCE_TYPE,
+        }
+    }
+
+    fn name(&self) -> String {
+        match *self {
+            ConfigResource::Topic(name) => name.to_owned(),
+            ConfigResource::Broker(id) => id.to_string(),
+        }
+    }
+}
+
+/// Groups the brokers by approximate release. Brokers whose API versions are unknown are not
+/// included.
+pub fn brokers_by_release(brokers: &[Broker]) -> BTreeMap<&'static str, Vec<BrokerId>> {
+    let mut releases = BTreeMap::new();
+    for broker in brokers {
+        if let Some(release) = broker.release() {
+            releases.entry(release).or_insert_with(Vec::new).push(broker.id);
+        }
+    }
+    releases
+}
+
+/// Connects to the broker, giving up after the timeout. `TcpStream::connect` doesn't take a
+/// timeout, so the connection is established in a separate thread.
+fn connect(host: &str, port: i32, timeout: Duration) -> Result<TcpStream> {
+    if CONNECTION_THREADS.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTION_THREADS {
+        CONNECTION_THREADS.fetch_sub(1, Ordering::SeqCst);
+        bail!("Too many pending connections, not connecting to {}:{}", host, port);
+    }
+    let guard = ConnectionThreadGuard;
+    let (sender, receiver) = mpsc::channel();
+    let address = (host.to_owned(), port as u16);
+    thread::Builder::new()
+        .name("Broker connection".into())
+        .spawn(move || {
+            let _guard = guard;
+            let _ = sender.send(TcpStream::connect((address.0.as_str(), address.1)));
+        })
+        .chain_err(|| "Failed to start connection thread")?;
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result.chain_err(|| format!("Failed to connect to {}:{}", host, port)),
+        Err(_) => bail!("Connection to {}:{} timed out", host, port),
+    }
+}
+
+/// Sends a single request to the broker, and returns the body of the response.
+fn send_request(host: &str, port: i32, api_key: i16, api_version: i16, body: &[u8], timeout: Duration)
+        -> Result<Vec<u8>> {
+    let mut request = Vec::new();
+    request.write_i16::<BigEndian>(api_key).unwrap();
+    request.write_i16::<BigEndian>(api_version).unwrap();
+    request.write_i32::<BigEndian>(0).unwrap();  // Correlation id
+    request.write_i16::<BigEndian>(CLIENT_ID.len() as i16).unwrap();
+    request.extend_from_slice(CLIENT_ID.as_bytes());
+    request.extend_from_slice(body);
+
+    let mut stream = connect(host, port, timeout)?;
+    stream.set_read_timeout(Some(timeout)).chain_err(|| "Failed to set read timeout")?;
+    stream.set_write_timeout(Some(timeout)).chain_err(|| "Failed to set write timeout")?;
+    stream.write_i32::<BigEndian>(request.len() as i32).chain_err(|| "Failed to send request size")?;
+    stream.write_all(&request).chain_err(|| "Failed to send request")?;
+
+    let size = stream.read_i32::<BigEndian>().chain_err(|| "Failed to read response size")?;
+    if size < 4 {
+        bail!("Invalid response size: {}", size);
+    }
+    let mut response = vec![0; size as usize];
+    stream.read_exact(&mut response).chain_err(|| "Failed to read response")?;
+    // Skips the correlation id
+    Ok(response.split_off(4))
+}
+
+fn write_str(buffer: &mut Vec<u8>, s: &str) {
+    buffer.write_i16::<BigEndian>(s.len() as i16).unwrap();
+    buffer.extend_from_slice(s.as_bytes());
+}
+
+fn read_nullable_str(rdr: &mut Cursor<&[u8]>) -> Result<Option<String>> {
+    let position = rdr.position();
+    let len = rdr.read_i16::<BigEndian>().chain_err(|| "Failed to parse string len")?;
+    if len < 0 {
+        return Ok(None);
+    }
+    rdr.set_position(position);
+    read_str(rdr).map(Some)
+}
+
+fn read_bool(rdr: &mut Cursor<&[u8]>) -> Result<bool> {
+    rdr.read_i8().map(|value| value != 0).chain_err(|| "Failed to parse boolean")
+}
+
+fn decode_api_versions(body: &[u8]) -> Result<Vec<ApiVersion>> {
+    let mut rdr = Cursor::new(body);
+    let error_code = rdr.read_i16::<BigEndian>().chain_err(|| "Failed to parse error code")?;
+    if error_code != 0 {
+        bail!("ApiVersions request failed with error code {}", error_code);
+    }
+    let count = read_array_len(&mut rdr)?;
+    let mut api_versions = Vec::with_capacity(count);
+    for _ in 0..count {
+        let api_key = rdr.read_i16::<BigEndian>().chain_err(|| "Failed to parse API key")?;
+        let min_version = rdr.read_i16::<BigEndian>().chain_err(|| "Failed to parse min version")?;
+        let max_version = rdr.read_i16::<BigEndian>().chain_err(|| "Failed to parse max version")?;
+        api_versions.push(ApiVersion { api_key: api_key, min_version: min_version, max_version: max_version });
+    }
+    Ok(api_versions)
+}
+
+fn decode_cluster_metadata(body: &[u8]) -> Result<ClusterMetadata> {
+    let mut rdr = Cursor::new(body);
+    let broker_count = read_array_len(&mut rdr)?;
+    let mut hosts = HashMap::new();
+    let mut racks = HashMap::new();
+    for _ in 0..broker_count {
+        let broker_id = rdr.read_i32::<BigEndian>().chain_err(|| "Failed to parse broker id")?;
+        let host = read_str(&mut rdr).chain_err(|| "Failed to parse broker host")?;
+        let port = rdr.read_i32::<BigEndian>().chain_err(|| "Failed to parse broker port")?;
+        hosts.insert(broker_id, (host, port));
+        if let Some(rack) = read_nullable_str(&mut rdr).chain_err(|| "Failed to parse broker rack")? {
+            racks.insert(broker_id, rack);
+        }
+    }
+    let controller_id = rdr.read_i32::<BigEndian>().chain_err(|| "Failed to parse controller id")?;
+    Ok(ClusterMetadata { controller_id: controller_id, hosts: hosts, racks: racks })
+}
+
+/// Decodes the configuration of each resource, by resource type and name.
+fn decode_describe_configs(body: &[u8]) -> Result<HashMap<(i8, String), Result<Vec<ConfigValue>>>> {
+    let mut rdr = Cursor::new(body);
+    let _throttle_time = rdr.read_i32::<BigEndian>().chain_err(|| "Failed to parse throttle time")?;
+    let resource_count = read_array_len(&mut rdr)?;
+    let mut results = HashMap::with_capacity(resource_count);
+    for _ in 0..resource_count {
+        let error_code = rdr.read_i16::<BigEndian>().chain_err(|| "Failed to parse error code")?;
+        let error_message = read_nullable_str(&mut rdr).chain_err(|| "Failed to parse error message")?;
+        let resource_type = rdr.read_i8().chain_err(|| "Failed to parse resource type")?;
+        let resource_name = read_str(&mut rdr).chain_err(|| "Failed to parse resource name")?;
+        let entry_count = read_array_len(&mut rdr)?;
+        let mut values = Vec::with_capacity(entry_count);
+        for _ in 0..entry_count {
+            let name = read_str(&mut rdr).chain_err(|| "Failed to parse config name")?;
+            let value = read_nullable_str(&mut rdr).chain_err(|| "Failed to parse config value")?;
+            let _read_only = read_bool(&mut rdr)?;
+            let is_default = read_bool(&mut rdr)?;
+            let _is_sensitive = read_bool(&mut rdr)?;
+            values.push(ConfigValue { name: name, value: value, is_default: is_default });
+        }
+        let result = if error_code == 0 {
+            Ok(values)
+        } else {
+            Err(format!("DescribeConfigs failed with error code {}: {}", error_code,
+                        error_message.unwrap_or_else(|| "no message".to_owned())).into())
+        };
+        results.insert((resource_type, resource_name), result);
+    }
+    Ok(results)
+}
+
+fn decode_create_topics(body: &[u8]) -> Result<Vec<(String, i16)>> {
+    let mut rdr = Cursor::new(body);
+    let topic_count = read_array_len(&mut rdr)?;
+    let mut errors = Vec::with_capacity(topic_count);
+    for _ in 0..topic_count {
+        let topic = read_str(&mut rdr).chain_err(|| "Failed to parse topic name")?;
+        let error_code = rdr.read_i16::<BigEndian>().chain_err(|| "Failed to parse error code")?;
+        errors.push((topic, error_code));
+    }
+    Ok(errors)
+}
+
+/// Returns the API versions supported by the broker. Brokers older than 0.10.0 don't support the
+/// request, and close the connection.
+pub fn fetch_api_versions(host: &str, port: i32, timeout: Duration) -> Result<Vec<ApiVersion>> {
+    let body = send_request(host, port, API_VERSIONS_KEY, 0, &[], timeout)
+        .chain_err(|| format!("ApiVersions request to {}:{} failed", host, port))?;
+    decode_api_versions(&body)
+}
+
+/// Returns the controller of the cluster and the racks of the brokers, as known by the broker.
+pub fn fetch_cluster_metadata(host: &str, port: i32, timeout: Duration) -> Result<ClusterMetadata> {
+    // Version 1 is the first one returning the controller. An empty topic list means no topic.
+    let mut request = Vec::new();
+    request.write_i32::<BigEndian>(0).unwrap();
+    let body = send_request(host, port, METADATA_KEY, 1, &request, timeout)
+        .chain_err(|| format!("Metadata request to {}:{} failed", host, port))?;
+    decode_cluster_metadata(&body)
+}
+
+/// Describes the configuration of the resources, including the default values. The results are
+/// in the same order as the resources. A broker only describes its own configuration. Requires
+/// Kafka 0.11 or later.
+pub fn describe_configs(host: &str, port: i32, resources: &[ConfigResource], timeout: Duration)
+        -> Result<Vec<Result<Vec<ConfigValue>>>> {
+    let mut request = Vec::new();
+    request.write_i32::<BigEndian>(resources.len() as i32).unwrap();
+    for resource in resources {
+        request.write_i8(resource.resource_type()).unwrap();
+        write_str(&mut request, &resource.name());
+        request.write_i32::<BigEndian>(-1).unwrap();  // All the configuration entries
+    }
+    let body = send_request(host, port, DESCRIBE_CONFIGS_KEY, 0, &request, timeout)
+        .chain_err(|| format!("DescribeConfigs request to {}:{} failed", host, port))?;
+    let mut results = decode_describe_configs(&body)?;
+    let values = resources.iter()
+        .map(|resource| {
+            results.remove(&(resource.resource_type(), resource.name()))
+                .unwrap_or_else(|| Err(format!("No configuration returned for {}", resource.name()).into()))
+        })
+        .collect();
+    Ok(values)
+}
+
+/// Creates a topic with the specified topic configuration. The request has to be sent to the
+/// controller. Requires Kafka 0.10.1 or later.
+pub fn create_topic(host: &str, port: i32, topic_name: &str, partitions: i32, replication_factor: i16,
+                    config: &[(&str, &str)], timeout: Duration) -> Result<()> {
+    let mut request = Vec::new();
+    request.write_i32::<BigEndian>(1).unwrap();
+    write_str(&mut request, topic_name);
+    request.write_i32::<BigEndian>(partitions).unwrap();
+    request.write_i16::<BigEndian>(replication_factor).unwrap();
+    request.write_i32::<BigEndian>(0).unwrap();  // No manual replica assignment
+    request.write_i32::<BigEndian>(config.len() as i32).unwrap();
+    for &(name, value) in config {
+        write_str(&mut request, name);
+        write_str(&mut request, value);
+    }
+    let timeout_ms = timeout.as_secs() * 1000 + (timeout.subsec_nanos() / 1_000_000) as u64;
+    request.write_i32::<BigEndian>(timeout_ms as i32).unwrap();
+    let body = send_request(host, port, CREATE_TOPICS_KEY, 0, &request, timeout)
+        .chain_err(|| format!("CreateTopics request to {}:{} failed", host, port))?;
+    for (topic, error_code) in decode_create_topics(&body)? {
+        if error_code != 0 {
+            bail!("Failed to create topic {}: error code {}", topic, error_code);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api(api_key: i16, max_version: i16) -> ApiVersion {
+        ApiVersion { api_key: api_key, min_version: 0, max_version: max_version }
+    }
+
+    #[test]
+    fn release_is_derived_from_api_versions() {
+        let mut response = Vec::new();
+        response.write_i16::<BigEndian>(0).unwrap();
+        response.write_i32::<BigEndian>(2).unwrap();
+        for &(key, max) in &[(1i16, 6i16), (37, 0)] {
+            response.write_i16::<BigEndian>(key).unwrap();
+            response.write_i16::<BigEndian>(0).unwrap();
+            response.write_i16::<BigEndian>(max).unwrap();
+        }
+        let versions = decode_api_versions(&response).unwrap();
+        assert_eq!(versions, vec![api(1, 6), api(37, 0)]);
+        assert_eq!(approximate_release(&versions), Some("1.0"));
+
+        assert_eq!(approximate_release(&[api(1, 10), api(37, 1), api(42, 1)]), Some("2.1"));
+        assert_eq!(approximate_release(&[api(18, 0), api(9, 1)]), Some("0.10.0"));
+        assert_eq!(approximate_release(&[]), None);
+    }
+
+    #[test]
+    fn racks_are_read_from_metadata() {
+        let mut response = Vec::new();
+        response.write_i32::<BigEndian>(2).unwrap();
+        for &(id, rack) in &[(1i32, Some("rack-a")), (2, None)] {
+            response.write_i32::<BigEndian>(id).unwrap();
+            response.write_i16::<BigEndian>(4).unwrap();
+            response.extend_from_slice(b"host");
+            response.write_i32::<BigEndian>(9092).unwrap();
+            match rack {
+                Some(rack) => {
+                    response.write_i16::<BigEndian>(rack.len() as i16).unwrap();
+                    response.extend_from_slice(rack.as_bytes());
+                },
+                None => response.write_i16::<BigEndian>(-1).unwrap(),
+            }
+        }
+        response.write_i32::<BigEndian>(2).unwrap();
+        let metadata = decode_cluster_metadata(&response).unwrap();
+        assert_eq!(metadata.controller_id, 2);
+        assert_eq!(metadata.racks.len(), 1);
+        assert_eq!(metadata.racks.get(&1).map(|rack| rack.as_str()), Some("rack-a"));
+        assert_eq!(metadata.hosts.get(&2), Some(&("host".to_owned(), 9092)));
+    }
+
+    #[test]
+    fn configs_are_read_from_describe_configs() {
+        let mut response = Vec::new();
+        response.write_i32::<BigEndian>(0).unwrap();
+        response.write_i32::<BigEndian>(2).unwrap();
+        // A topic with a single entry
+        response.write_i16::<BigEndian>(0).unwrap();
+        response.write_i16::<BigEndian>(-1).unwrap();
+        response.write_i8(TOPIC_RESOURCE_TYPE).unwrap();
+        write_str(&mut response, "t");
+        response.write_i32::<BigEndian>(1).unwrap();
+        write_str(&mut response, "cleanup.policy");
+        write_str(&mut response, "compact");
+        response.extend_from_slice(&[0, 0, 0]);
+        // A broker that failed
+        response.write_i16::<BigEndian>(42).unwrap();
+        write_str(&mut response, "failure");
+        response.write_i8(BROKER_RESOURCE_TYPE).unwrap();
+        write_str(&mut response, "1");
+        response.write_i32::<BigEndian>(0).unwrap();
+
+        let mut results = decode_describe_configs(&response).unwrap();
+        let topic_config = results.remove(&(TOPIC_RESOURCE_TYPE, "t".to_owned())).unwrap().unwrap();
+        assert_eq!(topic_config, vec![ConfigValue { name: "cleanup.policy".to_owned(),
+                                                    value: Some("compact".to_owned()), is_default: false }]);
+        assert!(results.remove(&(BROKER_RESOURCE_TYPE, "1".to_owned())).unwrap().is_err());
+    }
+}
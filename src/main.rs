@@ -22,6 +22,7 @@ extern crate mount;
 extern crate rand;
 extern crate rdkafka;
 extern crate regex;
+extern crate ring;
 extern crate router;
 extern crate serde;
 extern crate serde_cbor;
@@ -29,65 +30,159 @@ extern crate staticfile;
 extern crate urlencoded;
 
 #[macro_use] mod utils;
+mod admin;
 mod cache;
 mod config;
+mod distribution;
+mod election;
 mod error;
+mod events;
+mod group_protocol;
+mod health;
+mod kafka_api;
 mod metadata;
 mod metrics;
+mod rack_awareness;
 mod scheduler;
 mod web_server;
 mod offsets;
 
-use clap::{App, Arg, ArgMatches};
+use clap::{App, Arg, ArgMatches, SubCommand};
 
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time;
 use time::Duration;
 
-use cache::{Cache, ReplicaReader, ReplicaWriter};
+use cache::{Cache, Cipher, FileLogBackend, KafkaBackend, ReplicaBackend, ensure_replica_topic};
+use config::{CachingBackend, Config};
+use election::{Leadership, election_topic_name, ensure_election_topic, start_election};
 use error::*;
+use events::EventLog;
 use metrics::MetricsFetcher;
 use metadata::MetadataFetcher;
 
 use offsets::run_offset_consumer;
 
+/// Creates the backend of the cache. The replica topic is only created and checked if
+/// `ensure_topic` is set.
+fn create_cache_backend(config: &Config, ensure_topic: bool) -> Result<Arc<ReplicaBackend>> {
+    let caching = &config.caching;
+    let instance_id = caching.instance_id.clone()
+        .unwrap_or_else(|| format!("kafka-view-{:08x}", rand::random::<u32>()));
+    let backend: Arc<ReplicaBackend> = match caching.backend {
+        CachingBackend::Kafka => {
+            let (cluster_id, topic_name) = match (caching.cluster.as_ref(), caching.topic.as_ref()) {
+                (Some(cluster_id), Some(topic_name)) => (cluster_id, topic_name),
+                _ => bail!("The Kafka cache backend requires both 'cluster' and 'topic'"),
+            };
+            let replicator_bootstrap_servers = match config.cluster(cluster_id) {
+                Some(cluster) => cluster.bootstrap_servers(),
+                None => bail!("Can't find cache cluster {}", cluster_id),
+            };
+            info!("Using Kafka cache backend (brokers: {}, topic: {}, instance: {})",
+                  replicator_bootstrap_servers, topic_name, instance_id);
+            if ensure_topic {
+                ensure_replica_topic(&replicator_bootstrap_servers, topic_name, caching)
+                    .chain_err(|| "Replica topic validation failed")?;
+            }
+            let cipher = match (caching.encryption_key_file.as_ref(), caching.key_encryption_key_file.as_ref()) {
+                (Some(_), _) if caching.snapshot_path.is_some() =>
+                    bail!("Snapshots are written in plaintext, 'snapshot_path' can't be used with encryption"),
+                (Some(path), Some(key_encryption_key_path)) => {
+                    info!("Cache records are encrypted with the keys in {}", path);
+                    Some(Cipher::from_key_files(Path::new(path), Path::new(key_encryption_key_path))?)
+                },
+                (Some(_), None) => bail!("Encryption requires 'key_encryption_key_file'"),
+                (None, _) => None,
+            };
+            Arc::new(KafkaBackend::new(&replicator_bootstrap_servers, topic_name, &instance_id, cipher)?)
+        },
+        CachingBackend::File => {
+            let path = match caching.path.as_ref() {
+                Some(path) => path,
+                None => bail!("The file cache backend requires 'path'"),
+            };
+            info!("Using file cache backend (path: {})", path);
+            Arc::new(FileLogBackend::new(path, Duration::from_secs(caching.compaction_interval), &instance_id)?)
+        },
+    };
+    Ok(backend)
+}
+
+fn create_leadership(config: &Config) -> Result<Leadership> {
+    let caching = &config.caching;
+    if !caching.leader_election {
+        return Ok(Leadership::always_leader());
+    }
+    let (cluster_id, topic_name) = match (caching.backend.clone(), caching.cluster.as_ref(), caching.topic.as_ref()) {
+        (CachingBackend::Kafka, Some(cluster_id), Some(topic_name)) => (cluster_id, topic_name),
+        _ => bail!("Leader election requires the Kafka cache backend"),
+    };
+    let bootstrap_servers = match config.cluster(cluster_id) {
+        Some(cluster) => cluster.bootstrap_servers(),
+        None => bail!("Can't find cache cluster {}", cluster_id),
+    };
+    let election_topic = election_topic_name(topic_name);
+    ensure_election_topic(&bootstrap_servers, &election_topic, caching)
+        .chain_err(|| "Election topic validation failed")?;
+    start_election(&bootstrap_servers, &election_topic, &format!("kafka_view_election_{}", topic_name))
+}
+
+fn create_cache(config: &Config, ensure_topic: bool) -> Result<Cache> {
+    let backend = create_cache_backend(config, ensure_topic)
+        .chain_err(|| "Cache backend creation failed")?;
+    Ok(Cache::new(backend, &config.caching))
+}
+
 fn run_kafka_web(config_path: &str) -> Result<()> {
     let config = config::read_config(config_path)
         .chain_err(|| format!("Unable to load configuration from '{}'", config_path))?;
 
-    let replicator_bootstrap_servers = match config.cluster(&config.caching.cluster) {
-        Some(cluster) => cluster.bootstrap_servers(),
-        None => bail!("Can't find cache cluster {}", config.caching.cluster),
-    };
-    let topic_name = &config.caching.topic;
-    let replica_writer = ReplicaWriter::new(&replicator_bootstrap_servers, topic_name)
-        .chain_err(|| format!("Replica writer creation failed (brokers: {}, topic: {})", replicator_bootstrap_servers, topic_name))?;
-    let mut replica_reader = ReplicaReader::new(&replicator_bootstrap_servers, topic_name)
-        .chain_err(|| format!("Replica reader creation failed (brokers: {}, topic: {})", replicator_bootstrap_servers, topic_name))?;
+    let cache = create_cache(&config, true)?;
 
-    let cache = Cache::new(replica_writer);
+    // Load all the state from the snapshot and the cache backend
+    let snapshot_path = config.caching.snapshot_path.as_ref().map(PathBuf::from);
+    cache.load_state(snapshot_path.as_ref())
+        .chain_err(|| "State load failed")?;
+    cache.start_tailing()
+        .chain_err(|| "Failed to start following the cache updates")?;
+    if let Some(path) = snapshot_path {
+        cache.start_snapshots(path, Duration::from_secs(config.caching.snapshot_interval))
+            .chain_err(|| "Failed to start cache snapshots")?;
+    }
+    if !config.caching.ttl.is_empty() {
+        cache.start_expiry(Duration::from_secs(config.caching.expiry_interval))
+            .chain_err(|| "Failed to start cache expiry")?;
+    }
 
-    // Load all the state from Kafka
-    replica_reader.load_state(cache.alias())
-        .chain_err(|| format!("State load failed (brokers: {}, topic: {})", replicator_bootstrap_servers, topic_name))?;
+    let leadership = create_leadership(&config)
+        .chain_err(|| "Leader election failed to start")?;
+    if !config.caching.memory_limits.is_empty() {
+        cache.start_eviction(Duration::from_secs(config.caching.eviction_interval), leadership.clone())
+            .chain_err(|| "Failed to start cache eviction")?;
+    }
 
     // Metadata fetch
     let mut metadata_fetcher = MetadataFetcher::new(cache.brokers.alias(), cache.topics.alias(),
-            cache.groups.alias(), Duration::from_secs(config.metadata_refresh));
+            cache.groups.alias(), cache.topic_configs.alias(), cache.broker_configs.alias(),
+            EventLog::new(cache.events.alias(), config.event_log_size),
+            Duration::from_secs(config.metadata_refresh), leadership.clone());
     for (cluster_id, cluster_config) in &config.clusters {
         metadata_fetcher.add_cluster(cluster_id, &cluster_config)
             .chain_err(|| format!("Failed to add cluster {}", cluster_id))?;
-        run_offset_consumer(&cluster_id, &cluster_config, &config, cache.offsets.alias());
+        if cluster_config.consume_offsets {
+            run_offset_consumer(&cluster_id, &cluster_config, &config, cache.offsets.alias(), leadership.clone());
+        }
         info!("Added cluster {}", cluster_id);
     }
 
-    let mut metrics_fetcher = MetricsFetcher::new(cache.metrics.alias(),
-        Duration::from_secs(config.metrics_refresh));
-    for cluster_id in &cache.brokers.keys() {
-        // TODO there is a race condition here, a broker could be removed
-        for broker in cache.brokers.get(cluster_id).unwrap().iter() {
-            metrics_fetcher.add_broker(cluster_id, broker.id, &broker.hostname);
-        }
-    }
+    let metrics_fetcher = MetricsFetcher::new(cache.metrics.alias(),
+        Duration::from_secs(config.metrics_refresh), leadership.clone(), &config.clusters);
+    metrics_fetcher.follow_brokers(cache.brokers.alias())
+        .chain_err(|| "Failed to start metrics collection")?;
 
     web_server::server::run_server(cache.alias(), &config)
         .chain_err(|| "Server initialization failed")?;
@@ -95,6 +190,65 @@ fn run_kafka_web(config_path: &str) -> Result<()> {
     Ok(())
 }
 
+/// Writes the content of the cache as JSON lines, to the specified file or to stdout.
+fn run_cache_export(config_path: &str, output: Option<&str>) -> Result<()> {
+    let config = config::read_config(config_path)
+        .chain_err(|| format!("Unable to load configuration from '{}'", config_path))?;
+
+    // The export only reads the topic, and must not create it
+    let cache = create_cache(&config, false)?;
+    let snapshot_path = config.caching.snapshot_path.as_ref().map(PathBuf::from);
+    cache.load_state(snapshot_path.as_ref())
+        .chain_err(|| "State load failed")?;
+
+    let count = match output {
+        Some(path) => {
+            let file = File::create(path)
+                .chain_err(|| format!("Failed to create {}", path))?;
+            let mut writer = BufWriter::new(file);
+            let count = cache.export_json(&mut writer)?;
+            writer.flush().chain_err(|| format!("Failed to write {}", path))?;
+            count
+        },
+        None => {
+            let stdout = io::stdout();
+            let mut writer = BufWriter::new(stdout.lock());
+            let count = cache.export_json(&mut writer)?;
+            writer.flush().chain_err(|| "Failed to write to stdout")?;
+            count
+        },
+    };
+    info!("{} cache entries exported", count);
+    Ok(())
+}
+
+/// Writes to the cache the entries of a JSON lines export, read from the specified file or
+/// from stdin.
+fn run_cache_import(config_path: &str, input: Option<&str>) -> Result<()> {
+    let config = config::read_config(config_path)
+        .chain_err(|| format!("Unable to load configuration from '{}'", config_path))?;
+
+    let cache = create_cache(&config, true)?;
+    let count = match input {
+        Some(path) => {
+            let file = File::open(path)
+                .chain_err(|| format!("Failed to open {}", path))?;
+            cache.import_json(BufReader::new(file))?
+        },
+        None => {
+            let stdin = io::stdin();
+            let count = cache.import_json(stdin.lock())?;
+            count
+        },
+    };
+    let failed = cache.wait_writes(Duration::from_secs(60))?;
+    if failed > 0 {
+        bail!("{} of {} imported entries couldn't be written", failed, count);
+    }
+    info!("{} cache entries imported", count);
+    Ok(())
+}
+
 fn setup_args<'a>() -> ArgMatches<'a> {
     App::new("kafka web interface")
         .version(option_env!("CARGO_PKG_VERSION").unwrap_or(""))
@@ -109,6 +263,22 @@ fn setup_args<'a>() -> ArgMatches<'a> {
             .long("log-conf")
             .help("Configure the logging format (example: 'rdkafka=trace')")
             .takes_value(true))
+        .subcommand(SubCommand::with_name("cache")
+            .about("Cache maintenance")
+            .subcommand(SubCommand::with_name("export")
+                .about("Writes the content of the cache as JSON lines")
+                .arg(Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("Output file (default: stdout)")
+                    .takes_value(true)))
+            .subcommand(SubCommand::with_name("import")
+                .about("Writes to the cache the content of a JSON lines export")
+                .arg(Arg::with_name("input")
+                    .short("i")
+                    .long("input")
+                    .help("Input file (default: stdin)")
+                    .takes_value(true))))
         .get_matches()
 }
 
@@ -119,8 +289,18 @@ fn main() {
 
     let config_path = matches.value_of("conf").unwrap();
 
-    info!("Kafka-view is starting up!");
-    if let Err(e) = run_kafka_web(config_path) {
+    let result = match matches.subcommand() {
+        ("cache", Some(cache_matches)) => match cache_matches.subcommand() {
+            ("export", Some(export_matches)) => run_cache_export(config_path, export_matches.value_of("output")),
+            ("import", Some(import_matches)) => run_cache_import(config_path, import_matches.value_of("input")),
+            _ => Err("Missing cache command, use 'export' or 'import'".into()),
+        },
+        _ => {
+            info!("Kafka-view is starting up!");
+            run_kafka_web(config_path)
+        },
+    };
+    if let Err(e) = result {
         format_error_chain!(e);
         std::process::exit(1);
     }
@@ -1,21 +1,34 @@
 use rdkafka::consumer::{BaseConsumer, EmptyConsumerContext};
 use rdkafka::config::ClientConfig;
 use rdkafka::error as rderror;
+use serde_cbor;
 
-use cache::ReplicatedMap;
+use admin::{ConfigValue, KafkaAdmin, create_admin, describe_broker_configs, describe_topic_configs};
+use cache::{ReplicatedMap, Selection, TopicCache};
 use config::ClusterConfig;
+use election::Leadership;
 use error::*;
+use events::{EventLog, MetadataEvent, diff_brokers, diff_group, diff_topic};
+use group_protocol::{CONSUMER_PROTOCOL, decode_assignment, decode_subscription};
+use kafka_api::{ApiVersion, approximate_release, fetch_api_versions};
 use scheduler::{Scheduler, ScheduledTask};
 
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::borrow::Borrow;
+use std::cmp;
 use std::fmt;
-use std::sync::{Arc, RwLock};
-use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::collections::{HashMap, HashSet};
 
 
 pub type MetadataConsumer = BaseConsumer<EmptyConsumerContext>;
 
+/// The API versions of a broker are probed again after this interval, to detect upgrades.
+const API_VERSIONS_REFRESH_SECS: u64 = 3600;
+/// After a failed probe, a broker is not probed again for this interval, doubled at every
+/// consecutive failure up to `API_VERSIONS_REFRESH_SECS`.
+const PROBE_BACKOFF_SECS: u64 = 60;
+
 lazy_static! {
     pub static ref CONSUMERS: RwLock<HashMap<ClusterId, Arc<MetadataConsumer>>> = RwLock::new(HashMap::new());
 }
@@ -55,7 +68,7 @@ impl fmt::Display for ClusterId {
 // ********** METADATA **********
 //
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Partition {
     pub id: i32,
     pub leader: BrokerId,
@@ -76,11 +89,15 @@ impl Partition {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Broker {
     pub id: BrokerId,
     pub hostname: String,
-    pub port: i32
+    pub port: i32,
+    /// The API versions supported by the broker, if they could be fetched.
+    pub api_versions: Option<Vec<ApiVersion>>,
+    pub is_controller: bool,
+    pub rack: Option<String>,
 }
 
 impl Broker {
@@ -88,29 +105,207 @@ impl Broker {
         Broker {
             id: id,
             hostname: hostname,
-            port: port
+            port: port,
+            api_versions: None,
+            is_controller: false,
+            rack: None,
+        }
+    }
+
+    /// The Kafka release the broker is running, approximated from its API versions.
+    pub fn release(&self) -> Option<&'static str> {
+        self.api_versions.as_ref().and_then(|versions| approximate_release(versions))
+    }
+}
+
+#[derive(Deserialize)]
+struct BrokerV1 {
+    id: BrokerId,
+    hostname: String,
+    port: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BrokerV2 {
+    id: BrokerId,
+    hostname: String,
+    port: i32,
+    api_versions: Option<Vec<ApiVersion>>,
+    is_controller: bool,
+}
+
+/// Brokers stored before the API versions and the controller were recorded. They are filled in
+/// at the next metadata refresh.
+pub fn upgrade_brokers_v1(bytes: &[u8]) -> Result<Vec<u8>> {
+    let old = serde_cbor::from_slice::<Vec<BrokerV1>>(bytes).chain_err(|| "Failed to parse brokers")?;
+    let brokers = old.into_iter()
+        .map(|b| BrokerV2 { id: b.id, hostname: b.hostname, port: b.port, api_versions: None, is_controller: false })
+        .collect::<Vec<_>>();
+    serde_cbor::to_vec(&brokers).chain_err(|| "Failed to serialize brokers")
+}
+
+/// Brokers stored before the rack was recorded.
+pub fn upgrade_brokers_v2(bytes: &[u8]) -> Result<Vec<u8>> {
+    let old = serde_cbor::from_slice::<Vec<BrokerV2>>(bytes).chain_err(|| "Failed to parse brokers")?;
+    let brokers = old.into_iter()
+        .map(|b| Broker {
+            id: b.id,
+            hostname: b.hostname,
+            port: b.port,
+            api_versions: b.api_versions,
+            is_controller: b.is_controller,
+            rack: None,
+        })
+        .collect::<Vec<_>>();
+    serde_cbor::to_vec(&brokers).chain_err(|| "Failed to serialize brokers")
+}
+
+/// The id of the controller of the cluster, if known.
+pub fn controller_id(brokers: &[Broker]) -> Option<BrokerId> {
+    brokers.iter().find(|broker| broker.is_controller).map(|broker| broker.id)
+}
+
+/// A partition hosted by a broker.
+#[derive(Serialize, Debug, Clone)]
+pub struct BrokerPartition {
+    pub topic: TopicName,
+    pub partition: i32,
+    pub leader: bool,
+    pub in_sync: bool,
+}
+
+/// Number of partitions a broker leads, follows, and is out of sync for.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct BrokerSummary {
+    pub led: usize,
+    pub followed: usize,
+    pub out_of_isr: usize,
+}
+
+impl BrokerSummary {
+    fn add(&mut self, leader: bool, in_sync: bool) {
+        if leader {
+            self.led += 1;
+        } else {
+            self.followed += 1;
+        }
+        if !in_sync {
+            self.out_of_isr += 1;
         }
     }
 }
 
+/// Returns all the partitions replicated by the broker.
+pub fn broker_partitions(topic_cache: &TopicCache, cluster_id: &ClusterId, broker_id: BrokerId) -> Vec<BrokerPartition> {
+    topic_cache.lock_select_iter(&Selection::Cluster(cluster_id), |iter| {
+        iter.flat_map(|(&(_, ref topic_name), partitions)| {
+                partitions.iter()
+                    .filter(|p| p.replicas.contains(&broker_id))
+                    .map(move |p| BrokerPartition {
+                        topic: topic_name.clone(),
+                        partition: p.id,
+                        leader: p.leader == broker_id,
+                        in_sync: p.isr.contains(&broker_id),
+                    })
+            })
+            .collect::<Vec<_>>()
+    })
+}
+
+/// Summarizes the partitions of every broker of the selected topics.
+pub fn broker_summaries(topic_cache: &TopicCache, selection: &Selection) -> HashMap<(ClusterId, BrokerId), BrokerSummary> {
+    topic_cache.lock_select_iter(selection, |iter| {
+        let mut summaries: HashMap<(ClusterId, BrokerId), BrokerSummary> = HashMap::new();
+        for (&(ref cluster_id, _), partitions) in iter {
+            for p in partitions {
+                for &replica in &p.replicas {
+                    summaries.entry((cluster_id.clone(), replica))
+                        .or_insert_with(BrokerSummary::default)
+                        .add(p.leader == replica, p.isr.contains(&replica));
+                }
+            }
+        }
+        summaries
+    })
+}
+
 
 //
 // ********** GROUPS **********
 //
 
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct GroupMember {
     pub id: String,
     pub client_id: String,
     pub client_host: String,
+    /// Topics the member subscribed to (consumer protocol only).
+    pub subscription: Vec<TopicName>,
+    /// Partitions assigned to the member, by topic (consumer protocol only).
+    pub assignment: Vec<(TopicName, Vec<i32>)>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Group {
     pub name: String,
     pub state: String,
-    pub members: Vec<GroupMember>
+    pub members: Vec<GroupMember>,
+    pub protocol_type: String,
+    /// The assignment strategy, e.g. "range" or "roundrobin" for consumers.
+    pub protocol: String,
+}
+
+impl Group {
+    /// The id of the member each partition is assigned to.
+    pub fn partition_owners(&self) -> HashMap<(TopicName, i32), String> {
+        let mut owners = HashMap::new();
+        for member in &self.members {
+            for &(ref topic, ref partitions) in &member.assignment {
+                for &partition in partitions {
+                    owners.insert((topic.clone(), partition), member.id.clone());
+                }
+            }
+        }
+        owners
+    }
+}
+
+#[derive(Deserialize)]
+struct GroupMemberV1 {
+    id: String,
+    client_id: String,
+    client_host: String,
+}
+
+#[derive(Deserialize)]
+struct GroupV1 {
+    name: String,
+    state: String,
+    members: Vec<GroupMemberV1>,
+}
+
+/// Groups stored before the protocol and assignments were recorded. They are filled in at the
+/// next metadata refresh.
+pub fn upgrade_group_v1(bytes: &[u8]) -> Result<Vec<u8>> {
+    let old = serde_cbor::from_slice::<GroupV1>(bytes).chain_err(|| "Failed to parse group")?;
+    let members = old.members.into_iter()
+        .map(|m| GroupMember {
+            id: m.id,
+            client_id: m.client_id,
+            client_host: m.client_host,
+            subscription: Vec::new(),
+            assignment: Vec::new(),
+        })
+        .collect();
+    let group = Group {
+        name: old.name,
+        state: old.state,
+        members: members,
+        protocol_type: String::new(),
+        protocol: String::new(),
+    };
+    serde_cbor::to_vec(&group).chain_err(|| "Failed to serialize group")
 }
 
 fn fetch_groups(consumer: &MetadataConsumer, timeout_ms: i32) -> Result<Vec<Group>> {
@@ -119,17 +314,42 @@ fn fetch_groups(consumer: &MetadataConsumer, timeout_ms: i32) -> Result<Vec<Grou
 
     let mut groups = Vec::new();
     for rd_group in group_list.groups() {
+        let is_consumer = rd_group.protocol_type() == CONSUMER_PROTOCOL;
         let members = rd_group.members().iter()
-            .map(|m| GroupMember {
-                id: m.id().to_owned(),
-                client_id: m.client_id().to_owned(),
-                client_host: m.client_host().to_owned()
+            .map(|m| {
+                let metadata = if is_consumer { m.metadata() } else { None };
+                let subscription = match metadata.map(decode_subscription) {
+                    Some(Ok(subscription)) => subscription,
+                    Some(Err(e)) => {
+                        warn!("Can't decode subscription of {} in group {}: {}", m.id(), rd_group.name(), e);
+                        Vec::new()
+                    },
+                    None => Vec::new(),
+                };
+                let assignment_data = if is_consumer { m.assignment() } else { None };
+                let assignment = match assignment_data.map(decode_assignment) {
+                    Some(Ok(assignment)) => assignment,
+                    Some(Err(e)) => {
+                        warn!("Can't decode assignment of {} in group {}: {}", m.id(), rd_group.name(), e);
+                        Vec::new()
+                    },
+                    None => Vec::new(),
+                };
+                GroupMember {
+                    id: m.id().to_owned(),
+                    client_id: m.client_id().to_owned(),
+                    client_host: m.client_host().to_owned(),
+                    subscription: subscription,
+                    assignment: assignment,
+                }
             })
             .collect::<Vec<_>>();
         groups.push(Group {
             name: rd_group.name().to_owned(),
             state: rd_group.state().to_owned(),
-            members: members
+            members: members,
+            protocol_type: rd_group.protocol_type().to_owned(),
+            protocol: rd_group.protocol().to_owned(),
         })
     }
     Ok(groups)
@@ -139,41 +359,96 @@ fn fetch_groups(consumer: &MetadataConsumer, timeout_ms: i32) -> Result<Vec<Grou
 // TODO: remove and use MetadataFetcher directly
 struct MetadataFetcherTask {
     cluster_id: ClusterId,
+    cluster_config: ClusterConfig,
     consumer: Arc<MetadataConsumer>,
+    admin: Arc<KafkaAdmin>,
+    leadership: Leadership,
     broker_cache: ReplicatedMap<ClusterId, Vec<Broker>>,
     topic_cache: ReplicatedMap<(ClusterId, TopicName), Vec<Partition>>,
-    group_cache: ReplicatedMap<(ClusterId, String), Group>
+    group_cache: ReplicatedMap<(ClusterId, String), Group>,
+    topic_config_cache: ReplicatedMap<(ClusterId, TopicName), Vec<ConfigValue>>,
+    broker_config_cache: ReplicatedMap<(ClusterId, BrokerId), Vec<ConfigValue>>,
+    event_log: EventLog,
+    // The last API versions probe of each broker and hostname
+    api_versions_probes: Mutex<HashMap<(BrokerId, String), ProbeState>>,
+}
+
+struct ProbeState {
+    time: Instant,
+    failures: u32,
+}
+
+impl ProbeState {
+    /// Whether the broker should be probed again, given whether its API versions are known.
+    fn is_due(&self, known: bool) -> bool {
+        let interval = if self.failures == 0 {
+            if known { API_VERSIONS_REFRESH_SECS } else { 0 }
+        } else {
+            let backoff = PROBE_BACKOFF_SECS.saturating_mul(1 << cmp::min(self.failures - 1, 16));
+            cmp::min(backoff, API_VERSIONS_REFRESH_SECS)
+        };
+        self.time.elapsed() >= Duration::from_secs(interval)
+    }
 }
 
 impl MetadataFetcherTask {
     fn new(
         cluster_id: &ClusterId,
+        cluster_config: &ClusterConfig,
         consumer: Arc<MetadataConsumer>,
+        admin: Arc<KafkaAdmin>,
+        leadership: Leadership,
         broker_cache: ReplicatedMap<ClusterId, Vec<Broker>>,
         topic_cache: ReplicatedMap<(ClusterId, TopicName), Vec<Partition>>,
-        group_cache: ReplicatedMap<(ClusterId, String), Group>
+        group_cache: ReplicatedMap<(ClusterId, String), Group>,
+        topic_config_cache: ReplicatedMap<(ClusterId, TopicName), Vec<ConfigValue>>,
+        broker_config_cache: ReplicatedMap<(ClusterId, BrokerId), Vec<ConfigValue>>,
+        event_log: EventLog,
     ) -> MetadataFetcherTask {
         MetadataFetcherTask {
             cluster_id: cluster_id.to_owned(),
+            cluster_config: cluster_config.clone(),
             consumer: consumer,
+            admin: admin,
+            leadership: leadership,
             broker_cache: broker_cache,
             topic_cache: topic_cache,
             group_cache: group_cache,
+            topic_config_cache: topic_config_cache,
+            broker_config_cache: broker_config_cache,
+            event_log: event_log,
+            api_versions_probes: Mutex::new(HashMap::new()),
         }
     }
 }
 
 impl ScheduledTask for MetadataFetcherTask {
     fn run(&self) -> Result<()> {
-        let metadata = self.consumer.fetch_metadata(60000)
+        if !self.leadership.is_leader() {
+            return Ok(());
+        }
+        let metadata_timeout = (self.cluster_config.metadata_timeout * 1000) as i32;
+        let metadata = self.consumer.fetch_metadata(metadata_timeout)
             .chain_err(|| format!("Failed to fetch metadata from {}", self.cluster_id))?;
         let mut brokers = Vec::new();
         for broker in metadata.brokers() {
             brokers.push(Broker::new(broker.id(), broker.host().to_owned(), broker.port()));
         }
+        self.probe_brokers(&mut brokers);
+        let broker_ids = brokers.iter().map(|broker| broker.id).collect::<Vec<_>>();
+        // Changes are only detected once a previous snapshot of the cluster is available
+        let previous_brokers = self.broker_cache.get(&self.cluster_id);
+        let first_run = previous_brokers.is_none();
+        let mut events = previous_brokers
+            .map(|previous| diff_brokers(&previous, &brokers))
+            .unwrap_or_else(Vec::new);
         self.broker_cache.insert(self.cluster_id.to_owned(), brokers)
             .chain_err(|| "Failed to insert broker information in cache")?;
+        if let Err(e) = self.fetch_broker_configs(&broker_ids) {
+            format_error_chain!(e);
+        }
 
+        let mut topic_names = HashSet::with_capacity(metadata.topics().len());
         for topic in metadata.topics() {
             let mut partitions = Vec::with_capacity(topic.partitions().len());
             for p in topic.partitions() {
@@ -181,24 +456,211 @@ impl ScheduledTask for MetadataFetcherTask {
                                                p.error().map(|e| rderror::resp_err_description(e))));
             }
             partitions.sort_by(|a, b| a.id.cmp(&b.id));
-            self.topic_cache.insert((self.cluster_id.to_owned(), topic.name().to_owned()), partitions)
+            let key = (self.cluster_id.to_owned(), topic.name().to_owned());
+            if !first_run {
+                let previous = self.topic_cache.get(&key);
+                events.extend(diff_topic(topic.name(), previous.as_ref().map(|p| &p[..]), Some(&partitions)));
+            }
+            self.topic_cache.insert(key, partitions)
                 .chain_err(|| "Failed to insert broker information in cache")?;
+            topic_names.insert(topic.name().to_owned());
+        }
+        let deleted_topics = self.evict_deleted_topics(&topic_names)
+            .chain_err(|| "Failed to remove deleted topics from cache")?;
+        events.extend(deleted_topics.into_iter().map(|topic| MetadataEvent::TopicDeleted { topic: topic }));
+        self.event_log.append(&self.cluster_id, events)
+            .chain_err(|| "Failed to record metadata events")?;
+
+        // Fetch topic configurations. Brokers older than 0.11 can't describe them, but the rest
+        // of the metadata is still updated.
+        let topic_names = topic_names.into_iter().collect::<Vec<_>>();
+        if let Err(e) = self.fetch_topic_configs(&topic_names) {
+            format_error_chain!(e);
+        }
+
+        // Fetch groups. If disabled, the groups fetched previously are removed.
+        let mut group_names = HashSet::new();
+        let mut group_events = Vec::new();
+        let groups = if self.cluster_config.fetch_groups {
+            let group_list_timeout = (self.cluster_config.group_list_timeout * 1000) as i32;
+            fetch_groups(self.consumer.as_ref(), group_list_timeout)?
+        } else {
+            Vec::new()
+        };
+        for group in groups {
+            group_names.insert(group.name.to_owned());
+            let key = (self.cluster_id.to_owned(), group.name.to_owned());
+            group_events.extend(diff_group(self.group_cache.get(&key).as_ref(), &group));
+            self.group_cache.insert(key, group)
+                .chain_err(|| "Failed to insert group information in cache")?;
+        }
+        let dead_groups = self.evict_dead_groups(&group_names)
+            .chain_err(|| "Failed to remove dead groups from cache")?;
+        if self.cluster_config.fetch_groups {
+            group_events.extend(dead_groups.into_iter().map(|group| MetadataEvent::GroupRemoved { group: group }));
+        }
+        self.event_log.append(&self.cluster_id, group_events)
+            .chain_err(|| "Failed to record group events")?;
+
+        Ok(())
+    }
+}
+
+impl MetadataFetcherTask {
+    /// Fetches the API versions of every broker, and finds the controller and the rack of each
+    /// broker. The API versions are only probed again when a broker changes hostname, or after
+    /// `API_VERSIONS_REFRESH_SECS`; a broker that can't be probed is skipped until its backoff
+    /// expires. Whatever can't be fetched is copied from the previous metadata of the cluster.
+    fn probe_brokers(&self, brokers: &mut [Broker]) {
+        let timeout = Duration::from_secs(self.cluster_config.probe_timeout);
+        let previous_brokers = self.broker_cache.get(&self.cluster_id).unwrap_or_else(Vec::new);
+        for broker in brokers.iter_mut() {
+            if let Some(previous) = previous_brokers.iter().find(|previous| previous.id == broker.id) {
+                if previous.hostname == broker.hostname && previous.port == broker.port {
+                    broker.api_versions = previous.api_versions.clone();
+                }
+                broker.is_controller = previous.is_controller;
+                broker.rack = previous.rack.clone();
+            }
+        }
+
+        // The probes connect to the brokers directly, without any security layer
+        if self.cluster_config.security_protocol != "plaintext" {
+            return;
+        }
+
+        let mut probes = match self.api_versions_probes.lock() {
+            Ok(probes) => probes,
+            Err(_) => {
+                warn!("Poisoned lock on the API versions probes of {}", self.cluster_id);
+                return;
+            },
+        };
+        for broker in brokers.iter_mut() {
+            let key = (broker.id, broker.hostname.clone());
+            let failures = match probes.get(&key) {
+                Some(probe) if !probe.is_due(broker.api_versions.is_some()) => continue,
+                Some(probe) => probe.failures,
+                None => 0,
+            };
+            let failures = match fetch_api_versions(&broker.hostname, broker.port, timeout) {
+                Ok(api_versions) => {
+                    broker.api_versions = Some(api_versions);
+                    0
+                },
+                Err(e) => {
+                    warn!("Can't fetch API versions of broker {} in {}: {}", broker.id, self.cluster_id, e);
+                    failures + 1
+                },
+            };
+            probes.insert(key, ProbeState { time: Instant::now(), failures: failures });
+        }
+
+        match self.admin.cluster_metadata(timeout) {
+            Ok(cluster_metadata) => {
+                for broker in brokers.iter_mut() {
+                    broker.is_controller = broker.id == cluster_metadata.controller_id;
+                    broker.rack = cluster_metadata.racks.get(&broker.id).cloned();
+                }
+            },
+            Err(e) => warn!("Can't find the controller and the racks of {}: {}", self.cluster_id, e),
         }
+    }
 
-        // Fetch groups
-        for group in fetch_groups(self.consumer.as_ref(), 30000)? {
-            self.group_cache.insert((self.cluster_id.to_owned(), group.name.to_owned()), group);
+    /// Removes from the cache all the topics of the cluster that are not in the latest metadata,
+    /// and returns their names.
+    fn evict_deleted_topics(&self, topic_names: &HashSet<TopicName>) -> Result<Vec<TopicName>> {
+        let deleted_topics = self.topic_cache.lock_select_iter(&Selection::Cluster(&self.cluster_id), |iter| {
+            iter.filter(|&(&(_, ref t), _)| !topic_names.contains(t))
+                .map(|(k, _)| k.clone())
+                .collect::<Vec<_>>()
+        });
+        let mut deleted_names = Vec::with_capacity(deleted_topics.len());
+        for key in deleted_topics {
+            info!("Topic {} was removed from {}", key.1, self.cluster_id);
+            self.topic_cache.remove(&key)?;
+            self.topic_config_cache.remove(&key)?;
+            deleted_names.push(key.1);
         }
+        Ok(deleted_names)
+    }
 
+    /// Fetches the configuration of all the brokers, and removes the configuration of the brokers
+    /// that left the cluster.
+    fn fetch_broker_configs(&self, broker_ids: &[BrokerId]) -> Result<()> {
+        let removed_brokers = self.broker_config_cache.select_clone_k(&Selection::Cluster(&self.cluster_id))
+            .into_iter()
+            .filter(|&(_, id)| !broker_ids.contains(&id))
+            .collect::<Vec<_>>();
+        for key in removed_brokers {
+            self.broker_config_cache.remove(&key)?;
+        }
+        if broker_ids.is_empty() {
+            return Ok(());
+        }
+        let configs = describe_broker_configs(&self.admin, broker_ids, Duration::from_secs(30))
+            .chain_err(|| format!("Failed to fetch broker configurations from {}", self.cluster_id))?;
+        for (broker_id, result) in configs {
+            match result {
+                Ok(mut config) => {
+                    config.sort_by(|a, b| a.name.cmp(&b.name));
+                    self.broker_config_cache.insert((self.cluster_id.to_owned(), broker_id), config)
+                        .chain_err(|| "Failed to insert broker configuration in cache")?;
+                },
+                Err(e) => warn!("Can't describe configuration of broker {} in {}: {}", broker_id, self.cluster_id, e),
+            }
+        }
         Ok(())
     }
+
+    /// Fetches the configuration of all the topics with a single request. Topics whose
+    /// configuration can't be described keep the previous one.
+    fn fetch_topic_configs(&self, topic_names: &[TopicName]) -> Result<()> {
+        if topic_names.is_empty() {
+            return Ok(());
+        }
+        let configs = describe_topic_configs(&self.admin, topic_names, Duration::from_secs(30))
+            .chain_err(|| format!("Failed to fetch topic configurations from {}", self.cluster_id))?;
+        for (topic_name, result) in configs {
+            match result {
+                Ok(mut config) => {
+                    config.sort_by(|a, b| a.name.cmp(&b.name));
+                    self.topic_config_cache.insert((self.cluster_id.to_owned(), topic_name), config)
+                        .chain_err(|| "Failed to insert topic configuration in cache")?;
+                },
+                Err(e) => warn!("Can't describe configuration of topic {} in {}: {}", topic_name, self.cluster_id, e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes from the cache all the groups of the cluster that are not registered anymore, and
+    /// returns their names.
+    fn evict_dead_groups(&self, group_names: &HashSet<String>) -> Result<Vec<String>> {
+        let dead_groups = self.group_cache.lock_select_iter(&Selection::Cluster(&self.cluster_id), |iter| {
+            iter.filter(|&(&(_, ref g), _)| !group_names.contains(g))
+                .map(|(k, _)| k.clone())
+                .collect::<Vec<_>>()
+        });
+        let mut dead_names = Vec::with_capacity(dead_groups.len());
+        for key in dead_groups {
+            debug!("Group {} is not registered in {} anymore", key.1, self.cluster_id);
+            self.group_cache.remove(&key)?;
+            dead_names.push(key.1);
+        }
+        Ok(dead_names)
+    }
 }
 
 pub struct MetadataFetcher {
     scheduler: Scheduler<ClusterId, MetadataFetcherTask>,
+    leadership: Leadership,
     broker_cache: ReplicatedMap<ClusterId, Vec<Broker>>,
     topic_cache: ReplicatedMap<(ClusterId, TopicName), Vec<Partition>>,
-    group_cache: ReplicatedMap<(ClusterId, String), Group>
+    group_cache: ReplicatedMap<(ClusterId, String), Group>,
+    topic_config_cache: ReplicatedMap<(ClusterId, TopicName), Vec<ConfigValue>>,
+    broker_config_cache: ReplicatedMap<(ClusterId, BrokerId), Vec<ConfigValue>>,
+    event_log: EventLog,
 }
 
 impl MetadataFetcher {
@@ -206,13 +668,21 @@ impl MetadataFetcher {
         broker_cache: ReplicatedMap<ClusterId, Vec<Broker>>,
         topic_cache: ReplicatedMap<(ClusterId, TopicName), Vec<Partition>>,
         group_cache: ReplicatedMap<(ClusterId, String), Group>,
-        interval: Duration
+        topic_config_cache: ReplicatedMap<(ClusterId, TopicName), Vec<ConfigValue>>,
+        broker_config_cache: ReplicatedMap<(ClusterId, BrokerId), Vec<ConfigValue>>,
+        event_log: EventLog,
+        interval: Duration,
+        leadership: Leadership
     ) -> MetadataFetcher {
         MetadataFetcher {
             scheduler: Scheduler::new(interval, 4),
+            leadership: leadership,
             broker_cache: broker_cache,
             topic_cache: topic_cache,
             group_cache: group_cache,
+            topic_config_cache: topic_config_cache,
+            broker_config_cache: broker_config_cache,
+            event_log: event_log,
         }
     }
 
@@ -220,21 +690,42 @@ impl MetadataFetcher {
         let consumer = ClientConfig::new()
             .set("bootstrap.servers", &cluster_config.bootstrap_servers())
             .set("api.version.request", "true")
+            .set("security.protocol", &cluster_config.security_protocol)
             .create::<MetadataConsumer>()
             .expect("Consumer creation failed");
 
         let consumer_arc = Arc::new(consumer);
+        let admin = create_admin(&cluster_config.bootstrap_servers())
+            .chain_err(|| format!("Failed to create admin client for {}", cluster_id))?;
 
         CONSUMERS.write()
             .map(|mut cache| (*cache).insert(cluster_id.clone(), consumer_arc.clone()))
             .map_err(|_| ErrorKind::PoisonError("adding consumer to cache".to_owned()))?;
 
-        let mut task = MetadataFetcherTask::new(
-            cluster_id, consumer_arc, self.broker_cache.alias(),
-            self.topic_cache.alias(), self.group_cache.alias());
+        let task = MetadataFetcherTask::new(
+            cluster_id, cluster_config, consumer_arc, Arc::new(admin), self.leadership.clone(), self.broker_cache.alias(),
+            self.topic_cache.alias(), self.group_cache.alias(), self.topic_config_cache.alias(),
+            self.broker_config_cache.alias(), self.event_log.alias());
 
         // TODO: scheduler should receive a lambda
-        self.scheduler.add_task(cluster_id.to_owned(), task);
+        match cluster_config.metadata_refresh {
+            Some(secs) => self.scheduler.add_task_with_period(cluster_id.to_owned(), task, Duration::from_secs(secs)),
+            None => self.scheduler.add_task(cluster_id.to_owned(), task),
+        }
         Ok(())
     }
 }
+
+#[cfg(test)]
+pub mod test_fixtures {
+    use super::*;
+
+    pub fn partition(id: i32, leader: BrokerId, replicas: Vec<BrokerId>, isr: Vec<BrokerId>) -> Partition {
+        Partition { id: id, leader: leader, replicas: replicas, isr: isr, error: None }
+    }
+
+    pub fn broker(id: BrokerId, rack: Option<&str>) -> Broker {
+        Broker { id: id, hostname: "localhost".to_owned(), port: 9092, api_versions: None, is_controller: false,
+                 rack: rack.map(|rack| rack.to_owned()) }
+    }
+}
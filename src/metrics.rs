@@ -7,8 +7,11 @@ use regex::Regex;
 
 use std::time::Duration;
 use std::collections::HashMap;
+use std::thread;
 
-use cache::MetricsCache;
+use cache::{BrokerCache, CacheEvent, MetricsCache};
+use config::ClusterConfig;
+use election::Leadership;
 use error::*;
 use metadata::{ClusterId, BrokerId, Broker, TopicName};
 use scheduler::{Scheduler, ScheduledTask};
@@ -100,7 +103,7 @@ pub fn build_topic_metrics(cluster_id: &ClusterId, brokers: &Vec<Broker>, topic_
     result
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct BrokerMetrics {
     pub topics: HashMap<TopicName, (f64, f64)>,
 }
@@ -120,22 +123,27 @@ struct MetricsFetcherTask {
     broker_id: BrokerId,
     cache: MetricsCache,
     hostname: String,
+    leadership: Leadership,
 }
 
 impl MetricsFetcherTask {
-    fn new(cluster_id: ClusterId, broker_id: BrokerId, hostname: String, cache: MetricsCache)
-            -> MetricsFetcherTask {
+    fn new(cluster_id: ClusterId, broker_id: BrokerId, hostname: String, cache: MetricsCache,
+           leadership: Leadership) -> MetricsFetcherTask {
         MetricsFetcherTask {
             cluster_id: cluster_id,
             broker_id: broker_id,
             cache: cache,
             hostname: hostname,
+            leadership: leadership,
         }
     }
 }
 
 impl ScheduledTask for MetricsFetcherTask {
     fn run(&self) -> Result<()> {
+        if !self.leadership.is_leader() {
+            return Ok(());
+        }
         debug!("Starting fetch for {}", self.hostname);
         let start = UTC::now();
         let byte_rate_json = fetch_metrics_json(&self.hostname, 8778, "kafka.server:name=BytesInPerSec,*,type=BrokerTopicMetrics/FifteenMinuteRate")
@@ -161,20 +169,78 @@ impl ScheduledTask for MetricsFetcherTask {
 pub struct MetricsFetcher {
     scheduler: Scheduler<(ClusterId, BrokerId), MetricsFetcherTask>,
     cache: MetricsCache,
+    leadership: Leadership,
+    cluster_configs: HashMap<ClusterId, ClusterConfig>,
+    // Hostname of each broker with a scheduled task
+    brokers: HashMap<(ClusterId, BrokerId), String>,
 }
 
 impl MetricsFetcher {
-    pub fn new(cache: MetricsCache, interval: Duration) -> MetricsFetcher {
+    pub fn new(cache: MetricsCache, interval: Duration, leadership: Leadership,
+               cluster_configs: &HashMap<ClusterId, ClusterConfig>) -> MetricsFetcher {
         MetricsFetcher {
             scheduler: Scheduler::new(interval, 4),
             cache: cache,
+            leadership: leadership,
+            cluster_configs: cluster_configs.clone(),
+            brokers: HashMap::new(),
         }
     }
 
-    pub fn add_broker(&mut self, cluster_id: &ClusterId, broker_id: BrokerId, hostname: &str) -> Result<()> {
-        let task = MetricsFetcherTask::new(cluster_id.to_owned(), broker_id, hostname.to_owned(),
-                                           self.cache.alias());
-        self.scheduler.add_task((cluster_id.to_owned(), broker_id), task);
+    /// Schedules the metrics collection of the brokers in the cache, and keeps following the
+    /// changes of the cache in a background thread: tasks are added for new brokers, removed for
+    /// departed ones and restarted when the hostname of a broker changes.
+    pub fn follow_brokers(mut self, broker_cache: BrokerCache) -> Result<()> {
+        // Subscribing first, no change can be missed
+        let updates = broker_cache.subscribe();
+        for cluster_id in broker_cache.keys() {
+            let brokers = broker_cache.get(&cluster_id).unwrap_or_else(Vec::new);
+            self.update_cluster(&cluster_id, &brokers);
+        }
+        thread::Builder::new()
+            .name("Metrics brokers".into())
+            .spawn(move || {
+                for update in updates.iter() {
+                    match update {
+                        CacheEvent::Insert { key, value } | CacheEvent::Update { key, new: value, .. } =>
+                            self.update_cluster(&key, &value),
+                        CacheEvent::Delete { key, .. } => self.update_cluster(&key, &[]),
+                    }
+                }
+            })
+            .chain_err(|| "Failed to start metrics broker tracking thread")?;
         Ok(())
     }
+
+    /// Makes the scheduled tasks of the cluster match its brokers.
+    fn update_cluster(&mut self, cluster_id: &ClusterId, brokers: &[Broker]) {
+        let (enabled, period) = match self.cluster_configs.get(cluster_id) {
+            Some(config) => (config.fetch_metrics, config.metrics_refresh.map(Duration::from_secs)),
+            None => (true, None),
+        };
+        let brokers: &[Broker] = if enabled { brokers } else { &[] };
+        let departed = self.brokers.keys()
+            .filter(|&&(ref c, id)| c == cluster_id && !brokers.iter().any(|b| b.id == id))
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in departed {
+            info!("Stopping metrics collection for broker {} of {}", key.1, key.0);
+            self.scheduler.remove_task(&key);
+            self.brokers.remove(&key);
+        }
+        for broker in brokers {
+            let key = (cluster_id.to_owned(), broker.id);
+            if self.brokers.get(&key) == Some(&broker.hostname) {
+                continue;
+            }
+            debug!("Scheduling metrics collection for broker {} of {} ({})", broker.id, cluster_id, broker.hostname);
+            let task = MetricsFetcherTask::new(cluster_id.to_owned(), broker.id, broker.hostname.clone(),
+                                               self.cache.alias(), self.leadership.clone());
+            match period {
+                Some(period) => self.scheduler.add_task_with_period(key.clone(), task, period),
+                None => self.scheduler.add_task(key.clone(), task),
+            }
+            self.brokers.insert(key, broker.hostname.clone());
+        }
+    }
 }
@@ -1,11 +1,10 @@
 use byteorder::{BigEndian, ReadBytesExt};
-use futures::stream::Stream;
 use rdkafka::config::{ClientConfig, TopicConfig};
-use rdkafka::consumer::stream_consumer::StreamConsumer;
-use rdkafka::consumer::{Consumer, EmptyConsumerContext, CommitMode};
+use rdkafka::consumer::{BaseConsumer, Consumer, EmptyConsumerContext, CommitMode};
 
-use cache::{Cache, OffsetsCache};
+use cache::{Cache, GROUP_INDEX, OffsetsCache, Selection, TOPIC_INDEX};
 use config::{Config, ClusterConfig};
+use election::Leadership;
 use error::*;
 use metadata::{ClusterId, TopicName};
 
@@ -59,7 +58,13 @@ fn parse_message(key: &[u8], payload: &[u8]) -> Result<ConsumerUpdate> {
     }
 }
 
-fn create_consumer(brokers: &str, group_id: &str) -> StreamConsumer<EmptyConsumerContext> {
+/// How long a poll waits for a message. Leadership is checked after every poll, so the consumer
+/// stops within this time even if the offsets topic is idle.
+const POLL_TIMEOUT_MS: i32 = 1000;
+
+type OffsetsConsumer = BaseConsumer<EmptyConsumerContext>;
+
+fn create_consumer(brokers: &str, group_id: &str) -> OffsetsConsumer {
     let mut consumer = ClientConfig::new()
         .set("group.id", &group_id)
         .set("bootstrap.servers", brokers)
@@ -69,7 +74,7 @@ fn create_consumer(brokers: &str, group_id: &str) -> StreamConsumer<EmptyConsume
         .set_default_topic_config(TopicConfig::new()
             .set("auto.offset.reset", "smallest")
             .finalize())
-        .create::<StreamConsumer<_>>()
+        .create::<OffsetsConsumer>()
         .expect("Consumer creation failed");
 
     consumer.subscribe(&vec!["__consumer_offsets"])
@@ -107,28 +112,41 @@ fn update_global_cache(cluster_id: &ClusterId, local_cache: &HashMap<(String, St
     }
 }
 
-fn consume_offset_topic(cluster_id: ClusterId, mut consumer: StreamConsumer<EmptyConsumerContext>,
-                        cache: OffsetsCache) -> Result<()> {
+/// Writes the offsets read so far to the cache, and commits the position of the consumer, so
+/// that the next leader continues from there.
+fn flush_offsets(cluster_id: &ClusterId, consumer: &OffsetsConsumer,
+                 local_cache: &HashMap<(String, String), Vec<i64>>, cache: &OffsetsCache) {
+    trace!("Dumping local offset cache ({}: {} updates)", cluster_id, local_cache.len());
+    update_global_cache(cluster_id, local_cache, cache);
+    time!("Commit", consumer.position()
+           .and_then(|pos| consumer.commit(&pos, CommitMode::Sync))
+           .map_err(|e| warn!("Error while fetching the current position: {:?}", e))
+    );
+}
+
+/// Consumes the offsets topic while the instance is the leader. The buffered offsets are flushed
+/// before returning, and the consumer leaves the group when it's dropped.
+fn consume_offset_topic(cluster_id: &ClusterId, consumer: OffsetsConsumer,
+                        cache: &OffsetsCache, leadership: &Leadership) -> Result<()> {
     let mut local_cache = HashMap::new();
     let mut last_dump = Instant::now();
 
-    for message in consumer.start().wait() {
-        // Update the cache if needed - TODO: this doesn't work if messages are not being received
+    loop {
+        if !leadership.is_leader() {
+            info!("Stopping offsets consumer for {}, the instance is not the leader", cluster_id);
+            flush_offsets(cluster_id, &consumer, &local_cache, cache);
+            break;
+        }
+        let message = consumer.poll(POLL_TIMEOUT_MS);
+        // Update the cache if needed
         if (Instant::now() - last_dump) > Duration::from_secs(10) {
-            trace!("Dumping local offset cache ({}: {} updates)", cluster_id, local_cache.len());
-            update_global_cache(&cluster_id, &local_cache, &cache);
+            flush_offsets(cluster_id, &consumer, &local_cache, cache);
             local_cache = HashMap::with_capacity(local_cache.len());
             last_dump = Instant::now();
-            time!("Commit", consumer.position()
-                   .and_then(|pos| consumer.commit(&pos, CommitMode::Sync))
-                   .map_err(|e| warn!("Error while fetching the current position: {:?}", e))
-            );
         }
         match message {
-            Err(e) => {
-                warn!("Can't receive data from stream: {:?}", e);
-            },
-            Ok(Ok(m)) => {
+            Ok(None) => {},
+            Ok(Some(m)) => {
                 let key = match m.key_view::<[u8]>() {
                     None => &[],
                     Some(Ok(s)) => s,
@@ -145,7 +163,6 @@ fn consume_offset_topic(cluster_id: ClusterId, mut consumer: StreamConsumer<Empt
                         &[]
                     },
                 };
-                parse_message(key, payload);
                 match parse_message(key, payload) {
                     Ok(update) => match update {
                         ConsumerUpdate::SetCommit {group, topic, partition, offset} => {
@@ -157,7 +174,7 @@ fn consume_offset_topic(cluster_id: ClusterId, mut consumer: StreamConsumer<Empt
                     Err(e) => format_error_chain!(e),
                 };
             },
-            Ok(Err(e)) => {
+            Err(e) => {
                 warn!("Kafka error: {:?}", e);
             },
         };
@@ -165,13 +182,21 @@ fn consume_offset_topic(cluster_id: ClusterId, mut consumer: StreamConsumer<Empt
     Ok(())
 }
 
+/// Consumes the offsets topic of the cluster in a background thread. The consumer only runs while
+/// this instance is the leader, and is recreated when the instance is elected again.
 pub fn run_offset_consumer(cluster_id: &ClusterId, cluster_config: &ClusterConfig,
-                           config: &Config, offset_cache: OffsetsCache) {
-    let consumer = create_consumer(&cluster_config.bootstrap_servers(), &config.consumer_offsets_group_id);
-
+                           config: &Config, offset_cache: OffsetsCache, leadership: Leadership) {
+    let bootstrap_servers = cluster_config.bootstrap_servers();
+    let group_id = config.consumer_offsets_group_id.clone();
     let cluster_id_clone = cluster_id.clone();
     thread::spawn(move || {
-        consume_offset_topic(cluster_id_clone, consumer, offset_cache);
+        loop {
+            leadership.wait_leadership();
+            let consumer = create_consumer(&bootstrap_servers, &group_id);
+            if let Err(e) = consume_offset_topic(&cluster_id_clone, consumer, &offset_cache, &leadership) {
+                format_error_chain!(e);
+            }
+        }
     });
 }
 
@@ -184,14 +209,14 @@ pub trait OffsetStore {
 
 impl OffsetStore for Cache {
     fn offsets_by_cluster(&self, cluster: &ClusterId) -> Vec<((ClusterId, String, TopicName), Vec<i64>)> {
-        self.offsets.filter_clone(|&(ref c, _, _)| c == cluster)
+        self.offsets.select_clone(&Selection::Cluster(cluster))
     }
 
     fn offsets_by_cluster_topic(&self, cluster: &ClusterId, topic: &TopicName) -> Vec<((ClusterId, String, TopicName), Vec<i64>)> {
-        self.offsets.filter_clone(|&(ref c, _, ref t)| c == cluster && t == topic)
+        self.offsets.select_clone(&Selection::Index(cluster, TOPIC_INDEX, topic))
     }
 
     fn offsets_by_cluster_group(&self, cluster: &ClusterId, group: &String) -> Vec<((ClusterId, String, TopicName), Vec<i64>)> {
-        self.offsets.filter_clone(|&(ref c, ref g, _)| c == cluster && g == group)
+        self.offsets.select_clone(&Selection::Index(cluster, GROUP_INDEX, group))
     }
 }
@@ -0,0 +1,158 @@
+use cache::{Cache, Selection};
+use metadata::{Broker, BrokerId, ClusterId, Partition, TopicName};
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum RackViolationKind {
+    /// All the replicas of the partition are in the same rack: losing the rack makes the
+    /// partition unavailable.
+    #[serde(rename = "single_rack")]
+    SingleRack,
+    /// None of the replicas in some rack are in the ISR.
+    #[serde(rename = "isr_lost_rack")]
+    IsrLostRack,
+}
+
+impl RackViolationKind {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            RackViolationKind::SingleRack => "Single rack",
+            RackViolationKind::IsrLostRack => "ISR lost rack",
+        }
+    }
+}
+
+/// A partition whose replicas are not spread safely across racks.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RackViolation {
+    pub topic: TopicName,
+    pub partition: i32,
+    pub kind: RackViolationKind,
+    /// The rack hosting all the replicas, or the racks missing from the ISR.
+    pub racks: Vec<String>,
+}
+
+impl fmt::Display for RackViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            RackViolationKind::SingleRack =>
+                write!(f, "All replicas are in rack {}", self.racks.join(", ")),
+            RackViolationKind::IsrLostRack =>
+                write!(f, "No replica in the ISR from rack {}", self.racks.join(", ")),
+        }
+    }
+}
+
+/// The racks the brokers are in, sorted by name.
+pub fn broker_racks(brokers: &[Broker]) -> Vec<String> {
+    brokers.iter()
+        .filter_map(|broker| broker.rack.clone())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// The racks of a cluster, and the partitions violating rack awareness.
+#[derive(Serialize, Debug, Clone)]
+pub struct RackAwareness {
+    pub racks: Vec<String>,
+    pub violations: Vec<RackViolation>,
+}
+
+impl RackAwareness {
+    /// Checks the partitions against the racks of the brokers. Replicas on brokers without a
+    /// known rack are ignored, and nothing is reported unless the cluster spans at least two
+    /// racks.
+    pub fn compute(brokers: &[Broker], topics: &[(TopicName, Vec<Partition>)]) -> RackAwareness {
+        let rack_of = brokers.iter()
+            .filter_map(|broker| broker.rack.as_ref().map(|rack| (broker.id, rack.as_str())))
+            .collect::<HashMap<BrokerId, &str>>();
+        let mut awareness = RackAwareness {
+            racks: broker_racks(brokers),
+            violations: Vec::new(),
+        };
+        if awareness.racks.len() < 2 {
+            return awareness;
+        }
+        for &(ref topic_name, ref partitions) in topics {
+            for p in partitions {
+                let replica_racks = p.replicas.iter()
+                    .filter_map(|id| rack_of.get(id).cloned())
+                    .collect::<BTreeSet<_>>();
+                let all_known = p.replicas.iter().all(|id| rack_of.contains_key(id));
+                if p.replicas.len() > 1 && all_known && replica_racks.len() == 1 {
+                    awareness.violations.push(RackViolation {
+                        topic: topic_name.clone(),
+                        partition: p.id,
+                        kind: RackViolationKind::SingleRack,
+                        racks: replica_racks.iter().map(|&rack| rack.to_owned()).collect(),
+                    });
+                    continue;
+                }
+                let isr_racks = p.isr.iter()
+                    .filter_map(|id| rack_of.get(id).cloned())
+                    .collect::<BTreeSet<_>>();
+                let lost_racks = replica_racks.difference(&isr_racks)
+                    .map(|&rack| rack.to_owned())
+                    .collect::<Vec<_>>();
+                if !lost_racks.is_empty() {
+                    awareness.violations.push(RackViolation {
+                        topic: topic_name.clone(),
+                        partition: p.id,
+                        kind: RackViolationKind::IsrLostRack,
+                        racks: lost_racks,
+                    });
+                }
+            }
+        }
+        awareness
+    }
+
+    /// Checks the partitions of the cluster, or of a single topic, from the content of the cache.
+    /// Returns `None` if the cluster is unknown.
+    pub fn from_cache(cache: &Cache, cluster_id: &ClusterId, topic: Option<&str>) -> Option<RackAwareness> {
+        let brokers = match cache.brokers.get(cluster_id) {
+            Some(brokers) => brokers,
+            None => return None,
+        };
+        let mut topics = cache.topics.select_clone(&Selection::Cluster(cluster_id))
+            .into_iter()
+            .filter(|&((_, ref topic_name), _)| topic.is_none() || topic == Some(topic_name.as_str()))
+            .map(|((_, topic_name), partitions)| (topic_name, partitions))
+            .collect::<Vec<_>>();
+        topics.sort_by(|a, b| a.0.cmp(&b.0));
+        Some(RackAwareness::compute(&brokers, &topics))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use metadata::test_fixtures::{broker, partition};
+
+    #[test]
+    fn rack_violations_are_detected() {
+        let brokers = vec![broker(1, Some("a")), broker(2, Some("a")), broker(3, Some("b"))];
+        let topics = vec![("t".to_owned(), vec![
+            partition(0, 1, vec![1, 3], vec![1, 3]),
+            partition(1, 1, vec![1, 2], vec![1, 2]),
+            partition(2, 2, vec![2, 3], vec![2]),
+            partition(3, 3, vec![3], vec![3]),
+        ])];
+        let awareness = RackAwareness::compute(&brokers, &topics);
+
+        assert_eq!(awareness.racks, vec!["a".to_owned(), "b".to_owned()]);
+        assert_eq!(awareness.violations, vec![
+            RackViolation { topic: "t".to_owned(), partition: 1, kind: RackViolationKind::SingleRack,
+                            racks: vec!["a".to_owned()] },
+            RackViolation { topic: "t".to_owned(), partition: 2, kind: RackViolationKind::IsrLostRack,
+                            racks: vec!["b".to_owned()] },
+        ]);
+
+        let single_rack = RackAwareness::compute(&brokers[..2], &topics);
+        assert!(single_rack.violations.is_empty());
+    }
+}
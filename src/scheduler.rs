@@ -1,9 +1,9 @@
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use std::panic::{self, AssertUnwindSafe};
 use std::thread;
 use std::sync::{Arc, RwLock};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::collections::VecDeque;
-use std::cmp;
 
 use futures_cpupool::{Builder, CpuPool};
 use futures::{Future, BoxFuture};
@@ -14,8 +14,19 @@ pub trait ScheduledTask: Send + Sync + 'static {
     fn run(&self) -> Result<()>;
 }
 
+/// Minimum time between two runs started by the scheduler.
+const TICK: u64 = 100;
+
+struct TaskEntry<I, T> {
+    task: Arc<(I, T)>,
+    period: Duration,
+    next_run: Instant,
+    // Set while the task is running: a task is never run concurrently with itself
+    running: Arc<AtomicBool>,
+}
+
 pub struct Scheduler<I: Eq + Send + Sync + 'static, T: ScheduledTask> {
-    tasks: Arc<RwLock<Vec<Arc<(I, T)>>>>,
+    tasks: Arc<RwLock<Vec<TaskEntry<I, T>>>>,
     period: Duration,
     thread: Option<thread::JoinHandle<()>>,
     should_stop: Arc<AtomicBool>,
@@ -23,6 +34,7 @@ pub struct Scheduler<I: Eq + Send + Sync + 'static, T: ScheduledTask> {
 }
 
 impl<I: Eq + Send + Sync + 'static, T: ScheduledTask> Scheduler<I, T> {
+    /// Creates a new scheduler. `period` is used for the tasks added without a specific period.
     pub fn new(period: Duration, pool_size: usize) -> Scheduler<I, T> {
         Scheduler {
             tasks: Arc::new(RwLock::new(Vec::new())),
@@ -34,25 +46,45 @@ impl<I: Eq + Send + Sync + 'static, T: ScheduledTask> Scheduler<I, T> {
     }
 
     pub fn add_task(&mut self, id: I, task: T) {
+        let period = self.period;
+        self.add_task_with_period(id, task, period);
+    }
+
+    /// Adds a task run every `period`. If a task with the same id exists, it's replaced.
+    pub fn add_task_with_period(&mut self, id: I, task: T, period: Duration) {
         if self.thread.is_none() {
             let tasks_clone = self.tasks.clone();
-            let period_clone = self.period.clone();
             let should_stop_clone = self.should_stop.clone();
             let cpu_pool_clone = self.cpu_pool.clone();
             let builder = thread::Builder::new().name("Scheduler".into());
-            let thread = builder.spawn(move || scheduler_clock_loop(period_clone, tasks_clone,
-                                                                    cpu_pool_clone, should_stop_clone))
+            let thread = builder.spawn(move || scheduler_clock_loop(tasks_clone, cpu_pool_clone, should_stop_clone))
                 .unwrap();
             self.thread = Some(thread);
         }
         let mut tasks = self.tasks.write().unwrap();
+        // New tasks are started one tick apart
+        let entry = TaskEntry {
+            task: Arc::new((id, task)),
+            period: period,
+            next_run: Instant::now() + Duration::from_millis(TICK * tasks.len() as u64),
+            running: Arc::new(AtomicBool::new(false)),
+        };
         for i in 0..tasks.len() {
-            if tasks[i].0 == id {
-                tasks[i] = Arc::new((id, task));
+            if tasks[i].task.0 == entry.task.0 {
+                tasks[i] = entry;
                 return;
             }
         }
-        tasks.push(Arc::new((id, task)));
+        tasks.push(entry);
+    }
+
+    /// Removes the task, returning whether it was scheduled. A run already in progress is
+    /// completed.
+    pub fn remove_task(&mut self, id: &I) -> bool {
+        let mut tasks = self.tasks.write().unwrap();
+        let task_count = tasks.len();
+        tasks.retain(|entry| entry.task.0 != *id);
+        tasks.len() != task_count
     }
 
     pub fn stop(&self) {
@@ -60,13 +92,12 @@ impl<I: Eq + Send + Sync + 'static, T: ScheduledTask> Scheduler<I, T> {
     }
 }
 
-fn scheduler_clock_loop<I, T>(period: Duration, tasks: Arc<RwLock<Vec<Arc<(I, T)>>>>,
-                              cpu_pool: CpuPool, should_stop: Arc<AtomicBool>)
+fn scheduler_clock_loop<I, T>(tasks: Arc<RwLock<Vec<TaskEntry<I, T>>>>, cpu_pool: CpuPool,
+                              should_stop: Arc<AtomicBool>)
     where I: Eq + Send + Sync + 'static,
           T: ScheduledTask {
-    let mut index = 0;
     let mut futures: VecDeque<(Arc<AtomicBool>, BoxFuture<(), Error>)> = VecDeque::new();
-    thread::sleep(Duration::from_millis(100));  // Wait for task enqueuing
+    thread::sleep(Duration::from_millis(TICK));  // Wait for task enqueuing
     while !should_stop.load(Ordering::Relaxed) {
         // Removes completed futures from the deque
         loop {
@@ -86,28 +117,58 @@ fn scheduler_clock_loop<I, T>(period: Duration, tasks: Arc<RwLock<Vec<Arc<(I, T)
                 None => break,
             };
         }
-        let n_tasks = {
-            let tasks = tasks.read().unwrap();
-            let complete = Arc::new(AtomicBool::new(false));
-            let task_clone = tasks[index].clone();
-            let complete_clone = complete.clone();
-            let f = cpu_pool.spawn_fn(move || {
-                let res = task_clone.1.run();
-                complete_clone.store(true, Ordering::Relaxed);
-                res
-            });
-            futures.push_back((complete, f.boxed()));
-            index = (index + 1) % tasks.len();
-            tasks.len()
-        };
-        let interval = cmp::max(period / (n_tasks as u32), Duration::from_millis(100));
-        thread::sleep(interval);
+        // Starts the first task due, if it's not still running
+        {
+            let now = Instant::now();
+            let mut tasks = tasks.write().unwrap();
+            let due = tasks.iter_mut()
+                .filter(|entry| entry.next_run <= now && !entry.running.load(Ordering::Relaxed))
+                .min_by_key(|entry| entry.next_run);
+            if let Some(entry) = due {
+                let complete = Arc::new(AtomicBool::new(false));
+                let task_clone = entry.task.clone();
+                let complete_clone = complete.clone();
+                let running_clone = entry.running.clone();
+                entry.running.store(true, Ordering::Relaxed);
+                let f = cpu_pool.spawn_fn(move || {
+                    // A panicking task is reported like a failed run, and scheduled again
+                    let res = panic::catch_unwind(AssertUnwindSafe(|| task_clone.1.run()))
+                        .unwrap_or_else(|_| Err("Scheduled task panicked".into()));
+                    running_clone.store(false, Ordering::Relaxed);
+                    complete_clone.store(true, Ordering::Relaxed);
+                    res
+                });
+                futures.push_back((complete, f.boxed()));
+                entry.next_run = now + entry.period;
+            }
+        }
+        thread::sleep(Duration::from_millis(TICK));
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct PanickingTask {
+        runs: Arc<AtomicUsize>,
+    }
+
+    impl ScheduledTask for PanickingTask {
+        fn run(&self) -> Result<()> {
+            self.runs.fetch_add(1, Ordering::SeqCst);
+            panic!("Task failure");
+        }
+    }
+
     #[test]
-    fn it_works() {
+    fn panicking_tasks_are_run_again() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let mut scheduler = Scheduler::new(Duration::from_millis(10), 1);
+        scheduler.add_task(0, PanickingTask { runs: runs.clone() });
+        thread::sleep(Duration::from_millis(1000));
+        scheduler.stop();
+        assert!(runs.load(Ordering::SeqCst) >= 2);
     }
 }
@@ -1,3 +1,4 @@
+use chrono::{DateTime, UTC};
 use futures::{future, Future};
 use futures_cpupool::Builder;
 use iron::prelude::*;
@@ -7,16 +8,44 @@ use regex::Regex;
 use router::Router;
 use urlencoded::UrlEncodedQuery;
 
-use cache::Cache;
+use admin::ConfigValue;
+use cache::{Cache, GROUP_INDEX, Selection, TOPIC_INDEX};
+use distribution::{ClusterDistribution, ReplicaMatrix};
 use error::*;
-use metadata::{CONSUMERS, ClusterId, TopicName};
+use events::{ClusterEvent, cluster_events};
+use health::ClusterHealth;
+use metadata::{CONSUMERS, BrokerId, ClusterId, TopicName, broker_partitions, broker_summaries};
 use metrics::build_topic_metrics;
 use offsets::OffsetStore;
+use rack_awareness::RackAwareness;
 use utils::json_gzip_response;
 use web_server::server::CacheType;
 
 use std::collections::HashMap;
 
+/// Age in seconds of the oldest cache entry a response was built from, if known.
+fn data_age(oldest_update: Option<DateTime<UTC>>) -> Option<i64> {
+    oldest_update.map(|updated| UTC::now().signed_duration_since(updated).num_seconds())
+}
+
+/// Rows of a configuration table: name, value and whether the value is a default or an override.
+fn config_rows(config: Vec<ConfigValue>) -> Vec<::serde_json::Value> {
+    config.into_iter()
+        .map(|c| {
+            let source = if c.is_default { "default" } else { "override" };
+            json!((c.name, c.value, source))
+        })
+        .collect()
+}
+
+fn min_update(a: Option<DateTime<UTC>>, b: Option<DateTime<UTC>>) -> Option<DateTime<UTC>> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(if a < b { a } else { b }),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
 //
 // ********** TOPICS LIST **********
 //
@@ -31,7 +60,7 @@ pub fn cluster_topics(req: &mut Request) -> IronResult<Response> {
     }
 
     let brokers = brokers.unwrap();
-    let topics = cache.topics.filter_clone(|&(ref c, _)| c == &cluster_id);
+    let topics = cache.topics.select_clone(&Selection::Cluster(&cluster_id));
     let topic_metrics = build_topic_metrics(&cluster_id, &brokers, topics.len(), &cache.metrics);
 
     let mut result_data = Vec::with_capacity(topics.len());
@@ -43,7 +72,162 @@ pub fn cluster_topics(req: &mut Request) -> IronResult<Response> {
         result_data.push(json!((topic_name, partitions.len(), &errors, rate.0.round(), rate.1.round())));
     }
 
-    Ok(json_gzip_response(json!({"data": result_data})))
+    let data_age = json!({
+        "topics": data_age(cache.topics.oldest_update(&Selection::Cluster(&cluster_id))),
+        "metrics": data_age(cache.metrics.oldest_update(&Selection::Cluster(&cluster_id))),
+        "brokers": data_age(cache.brokers.oldest_update(&Selection::Cluster(&cluster_id))),
+    });
+    Ok(json_gzip_response(json!({"data": result_data, "data_age": data_age})))
+}
+
+//
+// ********** HEALTH **********
+//
+
+pub fn cluster_health(req: &mut Request) -> IronResult<Response> {
+    let cache = req.extensions.get::<CacheType>().unwrap();
+    let cluster_id = req.extensions.get::<Router>().unwrap().find("cluster_id").unwrap().into();
+
+    let health = match ClusterHealth::from_cache(cache, &cluster_id) {
+        Some(health) => health,
+        None => return Ok(Response::with((status::NotFound, ""))),
+    };
+
+    let data_age = json!({
+        "brokers": data_age(cache.brokers.oldest_update(&Selection::Cluster(&cluster_id))),
+        "topics": data_age(cache.topics.oldest_update(&Selection::Cluster(&cluster_id))),
+    });
+    Ok(json_gzip_response(json!({"data": health, "data_age": data_age})))
+}
+
+//
+// ********** DISTRIBUTION **********
+//
+
+fn topics_data_age(cache: &Cache, cluster_id: &ClusterId) -> ::serde_json::Value {
+    json!({
+        "brokers": data_age(cache.brokers.oldest_update(&Selection::Cluster(cluster_id))),
+        "topics": data_age(cache.topics.oldest_update(&Selection::Cluster(cluster_id))),
+    })
+}
+
+pub fn cluster_distribution(req: &mut Request) -> IronResult<Response> {
+    let cache = req.extensions.get::<CacheType>().unwrap();
+    let cluster_id = req.extensions.get::<Router>().unwrap().find("cluster_id").unwrap().into();
+
+    match ClusterDistribution::from_cache(cache, &cluster_id) {
+        Some(distribution) =>
+            Ok(json_gzip_response(json!({"data": distribution, "data_age": topics_data_age(cache, &cluster_id)}))),
+        None => Ok(Response::with((status::NotFound, ""))),
+    }
+}
+
+pub fn distribution_brokers(req: &mut Request) -> IronResult<Response> {
+    let cache = req.extensions.get::<CacheType>().unwrap();
+    let cluster_id = req.extensions.get::<Router>().unwrap().find("cluster_id").unwrap().into();
+
+    let distribution = match ClusterDistribution::from_cache(cache, &cluster_id) {
+        Some(distribution) => distribution,
+        None => return Ok(Response::with((status::NotFound, ""))),
+    };
+    let result_data = distribution.brokers.into_iter()
+        .map(|b| json!((b.broker_id, b.leaders, b.leaders - b.preferred_leaders, b.replicas)))
+        .collect::<Vec<_>>();
+    Ok(json_gzip_response(json!({"data": result_data, "data_age": topics_data_age(cache, &cluster_id)})))
+}
+
+pub fn distribution_topics(req: &mut Request) -> IronResult<Response> {
+    let cache = req.extensions.get::<CacheType>().unwrap();
+    let cluster_id = req.extensions.get::<Router>().unwrap().find("cluster_id").unwrap().into();
+
+    let distribution = match ClusterDistribution::from_cache(cache, &cluster_id) {
+        Some(distribution) => distribution,
+        None => return Ok(Response::with((status::NotFound, ""))),
+    };
+    let result_data = distribution.topics.into_iter()
+        .map(|t| json!((t.topic, t.partitions, t.non_preferred_leaders, (t.skew * 100.0).round() / 100.0)))
+        .collect::<Vec<_>>();
+    Ok(json_gzip_response(json!({"data": result_data, "data_age": topics_data_age(cache, &cluster_id)})))
+}
+
+pub fn distribution_matrix(req: &mut Request) -> IronResult<Response> {
+    let cache = req.extensions.get::<CacheType>().unwrap();
+    let cluster_id = req.extensions.get::<Router>().unwrap().find("cluster_id").unwrap().into();
+
+    match ReplicaMatrix::from_cache(cache, &cluster_id) {
+        Some(matrix) =>
+            Ok(json_gzip_response(json!({"data": matrix, "data_age": topics_data_age(cache, &cluster_id)}))),
+        None => Ok(Response::with((status::NotFound, ""))),
+    }
+}
+
+//
+// ********** RACK AWARENESS **********
+//
+
+fn rack_violations_response(cache: &Cache, cluster_id: &ClusterId, topic: Option<&str>) -> Response {
+    let awareness = match RackAwareness::from_cache(cache, cluster_id, topic) {
+        Some(awareness) => awareness,
+        None => return Response::with((status::NotFound, "")),
+    };
+    let result_data = awareness.violations.into_iter()
+        .map(|v| json!((v.topic.clone(), v.partition, v.kind.name(), v.to_string())))
+        .collect::<Vec<_>>();
+    json_gzip_response(json!({
+        "data": result_data,
+        "racks": awareness.racks,
+        "data_age": topics_data_age(cache, cluster_id),
+    }))
+}
+
+pub fn cluster_rack_violations(req: &mut Request) -> IronResult<Response> {
+    let cache = req.extensions.get::<CacheType>().unwrap();
+    let cluster_id = req.extensions.get::<Router>().unwrap().find("cluster_id").unwrap().into();
+
+    Ok(rack_violations_response(cache, &cluster_id, None))
+}
+
+pub fn topic_rack_violations(req: &mut Request) -> IronResult<Response> {
+    let cache = req.extensions.get::<CacheType>().unwrap();
+    let cluster_id = req.extensions.get::<Router>().unwrap().find("cluster_id").unwrap().into();
+    let topic_name = req.extensions.get::<Router>().unwrap().find("topic_name").unwrap();
+
+    Ok(rack_violations_response(cache, &cluster_id, Some(topic_name)))
+}
+
+//
+// ********** EVENTS **********
+//
+
+fn event_rows(events: Vec<ClusterEvent>) -> Vec<::serde_json::Value> {
+    events.into_iter()
+        .map(|e| json!((e.timestamp.to_rfc3339(), e.event.kind(), e.event.to_string())))
+        .collect()
+}
+
+pub fn cluster_events_list(req: &mut Request) -> IronResult<Response> {
+    let cache = req.extensions.get::<CacheType>().unwrap();
+    let cluster_id = req.extensions.get::<Router>().unwrap().find("cluster_id").unwrap().into();
+
+    let events = cluster_events(&cache.events, &cluster_id, None);
+    // Events are detected when the metadata is fetched
+    let data_age = json!({
+        "brokers": data_age(cache.brokers.oldest_update(&Selection::Cluster(&cluster_id))),
+        "topics": data_age(cache.topics.oldest_update(&Selection::Cluster(&cluster_id))),
+        "groups": data_age(cache.groups.oldest_update(&Selection::Cluster(&cluster_id))),
+    });
+    Ok(json_gzip_response(json!({"data": event_rows(events), "data_age": data_age})))
+}
+
+pub fn topic_events_list(req: &mut Request) -> IronResult<Response> {
+    let cache = req.extensions.get::<CacheType>().unwrap();
+    let cluster_id = req.extensions.get::<Router>().unwrap().find("cluster_id").unwrap().into();
+    let topic_name = req.extensions.get::<Router>().unwrap().find("topic_name").unwrap();
+
+    let events = cluster_events(&cache.events, &cluster_id, Some(topic_name));
+    let topic_key = (cluster_id.clone(), topic_name.to_owned());
+    let data_age = json!({"topics": data_age(cache.topics.info(&topic_key).and_then(|info| info.updated))});
+    Ok(json_gzip_response(json!({"data": event_rows(events), "data_age": data_age})))
 }
 
 //
@@ -65,10 +249,114 @@ pub fn cluster_brokers(req: &mut Request) -> IronResult<Response> {
         let rate = cache.metrics.get(&(cluster_id.to_owned(), broker.id))
             .and_then(|b_metrics| { b_metrics.topics.get("__TOTAL__").cloned() })
             .unwrap_or((-1f64, -1f64)); // TODO null instead?
-        result_data.push(json!((broker.id, broker.hostname, rate.0.round(), rate.1.round())));
+        let release = broker.release().unwrap_or("unknown");
+        let role = if broker.is_controller { "Controller" } else { "" };
+        result_data.push(json!((broker.id, broker.hostname, rate.0.round(), rate.1.round(), release, role)));
+    }
+
+    let data_age = json!({
+        "brokers": data_age(cache.brokers.oldest_update(&Selection::Cluster(&cluster_id))),
+        "metrics": data_age(cache.metrics.oldest_update(&Selection::Cluster(&cluster_id))),
+    });
+    Ok(json_gzip_response(json!({"data": result_data, "data_age": data_age})))
+}
+
+pub fn all_brokers(req: &mut Request) -> IronResult<Response> {
+    let cache = req.extensions.get::<CacheType>().unwrap();
+
+    let summaries = broker_summaries(&cache.topics, &Selection::All);
+    let mut result_data = Vec::new();
+    for (cluster_id, brokers) in cache.brokers.select_clone(&Selection::All) {
+        for broker in brokers {
+            let summary = summaries.get(&(cluster_id.clone(), broker.id)).cloned().unwrap_or_default();
+            let rate = cache.metrics.get(&(cluster_id.clone(), broker.id))
+                .and_then(|b_metrics| { b_metrics.topics.get("__TOTAL__").cloned() })
+                .unwrap_or((-1f64, -1f64));
+            result_data.push(json!((cluster_id, broker.id, broker.hostname, broker.port, summary.led,
+                                    summary.followed, summary.out_of_isr, rate.0.round(), rate.1.round())));
+        }
+    }
+
+    let data_age = json!({
+        "brokers": data_age(cache.brokers.oldest_update(&Selection::All)),
+        "topics": data_age(cache.topics.oldest_update(&Selection::All)),
+        "metrics": data_age(cache.metrics.oldest_update(&Selection::All)),
+    });
+    Ok(json_gzip_response(json!({"data": result_data, "data_age": data_age})))
+}
+
+//
+// ********** BROKER **********
+//
+
+fn broker_key(req: &Request) -> Option<(ClusterId, BrokerId)> {
+    let router = req.extensions.get::<Router>().unwrap();
+    let cluster_id: ClusterId = router.find("cluster_id").unwrap().into();
+    router.find("broker_id").unwrap().parse::<BrokerId>().ok()
+        .map(|broker_id| (cluster_id, broker_id))
+}
+
+pub fn broker_partitions_list(req: &mut Request) -> IronResult<Response> {
+    let cache = req.extensions.get::<CacheType>().unwrap();
+    let (cluster_id, broker_id) = match broker_key(req) {
+        Some(key) => key,
+        None => return Ok(Response::with((status::NotFound, ""))),
+    };
+
+    let partitions = broker_partitions(&cache.topics, &cluster_id, broker_id);
+    let mut result_data = Vec::with_capacity(partitions.len());
+    for p in partitions {
+        let role = if p.leader { "leader" } else { "follower" };
+        let error = if p.in_sync { None } else { Some("Out of ISR") };
+        result_data.push(json!((p.topic, p.partition, role, error)));
+    }
+
+    let data_age = json!({
+        "topics": data_age(cache.topics.oldest_update(&Selection::Cluster(&cluster_id))),
+    });
+    Ok(json_gzip_response(json!({"data": result_data, "data_age": data_age})))
+}
+
+pub fn broker_topics(req: &mut Request) -> IronResult<Response> {
+    let cache = req.extensions.get::<CacheType>().unwrap();
+    let broker_key = match broker_key(req) {
+        Some(key) => key,
+        None => return Ok(Response::with((status::NotFound, ""))),
+    };
+
+    let (metrics, info) = match cache.metrics.get_with_info(&broker_key) {
+        Some(metrics_and_info) => metrics_and_info,
+        None => return Ok(Response::with((status::NotFound, ""))),
+    };
+    let mut result_data = Vec::with_capacity(metrics.topics.len());
+    for (topic_name, rate) in metrics.topics {
+        if topic_name != "__TOTAL__" {
+            result_data.push(json!((topic_name, rate.0.round(), rate.1.round())));
+        }
     }
 
-    Ok(json_gzip_response(json!({"data": result_data})))
+    let data_age = json!({
+        "metrics": data_age(info.updated),
+    });
+    Ok(json_gzip_response(json!({"data": result_data, "data_age": data_age})))
+}
+
+pub fn broker_config(req: &mut Request) -> IronResult<Response> {
+    let cache = req.extensions.get::<CacheType>().unwrap();
+    let broker_key = match broker_key(req) {
+        Some(key) => key,
+        None => return Ok(Response::with((status::NotFound, ""))),
+    };
+
+    let (config, info) = match cache.broker_configs.get_with_info(&broker_key) {
+        Some(config_and_info) => config_and_info,
+        None => return Ok(Response::with((status::NotFound, ""))),
+    };
+
+    let data_age = json!({
+        "broker_configs": data_age(info.updated),
+    });
+    Ok(json_gzip_response(json!({"data": config_rows(config), "data_age": data_age})))
 }
 
 //
@@ -96,16 +384,21 @@ impl GroupInfo {
 }
 
 // TOOD: add doc
-fn build_group_list<F>(cache: &Cache, filter_fn: F) -> HashMap<(ClusterId, String), GroupInfo>
+fn build_group_list<F>(cache: &Cache, groups: &Selection, offsets: &Selection, filter_fn: F)
+        -> HashMap<(ClusterId, String), GroupInfo>
         where F: Fn(&ClusterId, &TopicName, &String) -> bool {
 
-    let mut groups: HashMap<(ClusterId, String), GroupInfo> = cache.groups.lock_iter(|iter| {
+    let mut groups: HashMap<(ClusterId, String), GroupInfo> = cache.groups.lock_select_iter(groups, |iter| {
             iter.filter(|&(&(ref c, ref t), ref g)| filter_fn(&c, &t, &g.name))
                 .map(|(&(ref c, _), g)| ((c.clone(), g.name.clone()), GroupInfo::new(g.state.clone(), g.members.len())))
                 .collect()
         });
 
-    let offsets = cache.offsets.filter_clone_k(|&(ref c, ref g, ref t)| filter_fn(c, t, g));
+    let offsets = cache.offsets.lock_select_iter(offsets, |iter| {
+            iter.filter(|&(&(ref c, ref g, ref t), _)| filter_fn(c, t, g))
+                .map(|(k, _)| k.clone())
+                .collect::<Vec<_>>()
+        });
     for (cluster_id, group, _) in offsets {
         (*groups.entry((cluster_id, group)).or_insert(GroupInfo::new_empty())).add_offset();
     }
@@ -122,14 +415,19 @@ pub fn cluster_groups(req: &mut Request) -> IronResult<Response> {
         return Ok(Response::with((status::NotFound, "")));
     }
 
-    let groups = build_group_list(cache, |c, _, _| &cluster_id == c);
+    let selection = Selection::Cluster(&cluster_id);
+    let groups = build_group_list(cache, &selection, &selection, |_, _, _| true);
 
     let mut result_data = Vec::with_capacity(groups.len());
     for ((cluster_id, group_name), info) in groups {
         result_data.push(json!((group_name, info.state, info.members, info.stored_offsets)));
     }
 
-    let result = json!({"data": result_data});
+    let data_age = json!({
+        "groups": data_age(cache.groups.oldest_update(&selection)),
+        "offsets": data_age(cache.offsets.oldest_update(&selection)),
+    });
+    let result = json!({"data": result_data, "data_age": data_age});
     Ok(json_gzip_response(result))
 }
 
@@ -143,14 +441,20 @@ pub fn topic_groups(req: &mut Request) -> IronResult<Response> {
         return Ok(Response::with((status::NotFound, "")));
     }
 
-    let groups = build_group_list(cache, |c, t, _| &cluster_id == c && topic_name == t);
+    let offsets_selection = Selection::Index(&cluster_id, TOPIC_INDEX, topic_name);
+    let groups = build_group_list(cache, &Selection::Cluster(&cluster_id), &offsets_selection,
+                                  |_, t, _| topic_name == t);
 
     let mut result_data = Vec::with_capacity(groups.len());
     for ((cluster_id, group_name), info) in groups {
         result_data.push(json!((group_name, info.state, info.members, info.stored_offsets)));
     }
 
-    Ok(json_gzip_response(json!({"data": result_data})))
+    let data_age = json!({
+        "groups": data_age(cache.groups.oldest_update(&Selection::Cluster(&cluster_id))),
+        "offsets": data_age(cache.offsets.oldest_update(&offsets_selection)),
+    });
+    Ok(json_gzip_response(json!({"data": result_data, "data_age": data_age})))
 }
 
 pub fn group_members(req: &mut Request) -> IronResult<Response> {
@@ -167,10 +471,19 @@ pub fn group_members(req: &mut Request) -> IronResult<Response> {
 
     let mut result_data = Vec::with_capacity(group.members.len());
     for member in group.members {
-        result_data.push(json!((member.id, member.client_id, member.client_host)));
+        let assignment = member.assignment.iter()
+            .map(|&(ref topic, ref partitions)| {
+                let partitions = partitions.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+                format!("{}: {}", topic, partitions.join(", "))
+            })
+            .collect::<Vec<_>>();
+        result_data.push(json!((member.id, member.client_id, member.client_host, assignment.join("; "))));
     }
 
-    Ok(json_gzip_response(json!({"data": result_data})))
+    let data_age = json!({
+        "groups": data_age(cache.groups.info(&(cluster_id.clone(), group_name.to_owned())).and_then(|info| info.updated)),
+    });
+    Ok(json_gzip_response(json!({"data": result_data, "data_age": data_age})))
 }
 
 pub fn group_offsets(req: &mut Request) -> IronResult<Response> {
@@ -179,6 +492,9 @@ pub fn group_offsets(req: &mut Request) -> IronResult<Response> {
     let group_name = req.extensions.get::<Router>().unwrap().find("group_name").unwrap();
 
     let offsets = cache.offsets_by_cluster_group(&cluster_id, &group_name.to_owned());
+    let owners = cache.groups.get(&(cluster_id.clone(), group_name.to_owned()))
+        .map(|group| group.partition_owners())
+        .unwrap_or_else(HashMap::new);
 
     let wms = time!("fetch wms", fetch_watermarks(&cluster_id, &offsets));
     let wms = match wms {
@@ -201,11 +517,15 @@ pub fn group_offsets(req: &mut Request) -> IronResult<Response> {
                 (_, lag) if lag < 0 => "Out of retention".to_owned(),
                 _ => lag.to_string()
             };
-            result_data.push(json!((topic.clone(), partition_id, low, high, offset, lag_shown)));
+            let owner = owners.get(&(topic.clone(), partition_id as i32)).cloned().unwrap_or_default();
+            result_data.push(json!((topic.clone(), partition_id, low, high, offset, lag_shown, owner)));
         }
     }
 
-    Ok(json_gzip_response(json!({"data": result_data})))
+    let data_age = json!({
+        "offsets": data_age(cache.offsets.oldest_update(&Selection::Index(&cluster_id, GROUP_INDEX, group_name))),
+    });
+    Ok(json_gzip_response(json!({"data": result_data, "data_age": data_age})))
 }
 
 fn fetch_watermarks(cluster_id: &ClusterId, offsets: &Vec<((ClusterId, String, TopicName), Vec<i64>)>)
@@ -262,7 +582,28 @@ pub fn topic_topology(req: &mut Request) -> IronResult<Response> {
         result_data.push(json!((p.id, p.leader, p.replicas, p.isr, p.error)));
     }
 
-    Ok(json_gzip_response(json!({"data": result_data})))
+    let topic_key = (cluster_id, topic_name.to_owned());
+    let data_age = json!({
+        "topics": data_age(cache.topics.info(&topic_key).and_then(|info| info.updated)),
+    });
+    Ok(json_gzip_response(json!({"data": result_data, "data_age": data_age})))
+}
+
+pub fn topic_config(req: &mut Request) -> IronResult<Response> {
+    let cache = req.extensions.get::<CacheType>().unwrap();
+    let cluster_id: ClusterId = req.extensions.get::<Router>().unwrap().find("cluster_id").unwrap().into();
+    let topic_name = req.extensions.get::<Router>().unwrap().find("topic_name").unwrap();
+
+    let topic_key = (cluster_id, topic_name.to_owned());
+    let (config, info) = match cache.topic_configs.get_with_info(&topic_key) {
+        Some(config_and_info) => config_and_info,
+        None => return Ok(Response::with((status::NotFound, ""))),
+    };
+
+    let data_age = json!({
+        "topic_configs": data_age(info.updated),
+    });
+    Ok(json_gzip_response(json!({"data": config_rows(config), "data_age": data_age})))
 }
 
 //
@@ -283,22 +624,26 @@ pub fn consumer_search(req: &mut Request) -> IronResult<Response> {
     let groups = match (search_string, regex) {
         (pattern, "true") => {
             Regex::new(search_string)
-                .map(|r| build_group_list(cache, |_, _, g| r.is_match(g)))
+                .map(|r| build_group_list(cache, &Selection::All, &Selection::All, |_, _, g| r.is_match(g)))
                 .unwrap_or(HashMap::new())
         },
         (search, _) if search.len() >= 3 => {
-            build_group_list(cache, |_, _, g| g.contains(search))
+            build_group_list(cache, &Selection::All, &Selection::All, |_, _, g| g.contains(search))
         },
         _ => HashMap::new(),
     };
 
 
     let mut result_data = Vec::with_capacity(groups.len());
+    let mut oldest_update = None;
     for ((cluster_id, group_name), info) in groups {
+        let group_key = (cluster_id.clone(), group_name.clone());
+        oldest_update = min_update(oldest_update, cache.groups.info(&group_key).and_then(|info| info.updated));
         result_data.push(json!((cluster_id, group_name, info.state, info.members, info.stored_offsets)));
     }
 
-    Ok(json_gzip_response(json!({"data": result_data})))
+    let data_age = json!({"groups": data_age(oldest_update)});
+    Ok(json_gzip_response(json!({"data": result_data, "data_age": data_age})))
 }
 
 pub fn topic_search(req: &mut Request) -> IronResult<Response> {
@@ -326,7 +671,10 @@ pub fn topic_search(req: &mut Request) -> IronResult<Response> {
 
     let mut metrics_map = HashMap::new();
     let mut result_data = Vec::new();
+    let mut oldest_update = None;
     for ((cluster_id, topic_name), partitions) in topics {
+        let topic_key = (cluster_id.clone(), topic_name.clone());
+        oldest_update = min_update(oldest_update, cache.topics.info(&topic_key).and_then(|info| info.updated));
         let cluster_metrics = metrics_map.entry(cluster_id.clone())
             .or_insert_with(|| {
                 cache.brokers.get(&cluster_id)
@@ -339,5 +687,43 @@ pub fn topic_search(req: &mut Request) -> IronResult<Response> {
         result_data.push(json!((cluster_id, topic_name, partitions.len(), errors, b_rate, m_rate)));
     }
 
-    Ok(json_gzip_response(json!({"data": result_data})))
+    let data_age = json!({"topics": data_age(oldest_update)});
+    Ok(json_gzip_response(json!({"data": result_data, "data_age": data_age})))
+}
+
+//
+// ********** INTERNAL **********
+//
+
+pub fn cache_writes(req: &mut Request) -> IronResult<Response> {
+    let cache = req.extensions.get::<CacheType>().unwrap();
+
+    Ok(json_gzip_response(json!({"data": cache.write_stats()})))
+}
+
+pub fn cache_status(req: &mut Request) -> IronResult<Response> {
+    let cache = req.extensions.get::<CacheType>().unwrap();
+
+    let status = json!({
+        "maps": cache.map_stats(),
+        "writes": cache.write_stats(),
+        "replica_lag": cache.replica_lag(),
+    });
+    Ok(json_gzip_response(json!({"data": status})))
+}
+
+pub fn cache_lookup(req: &mut Request) -> IronResult<Response> {
+    let params = req.get_ref::<UrlEncodedQuery>().unwrap_or(&HashMap::new()).clone();
+    let cache = req.extensions.get::<CacheType>().unwrap();
+    let map_name = req.extensions.get::<Router>().unwrap().find("map_name").unwrap();
+
+    let key = match params.get("key") {
+        Some(results) => &results[0],
+        None => return Ok(Response::with((status::BadRequest, "Missing key"))),
+    };
+    match cache.lookup_json(map_name, key) {
+        Ok(Some((value, info))) => Ok(json_gzip_response(json!({"data": {"value": value, "info": info}}))),
+        Ok(None) => Ok(Response::with((status::NotFound, ""))),
+        Err(e) => Ok(Response::with((status::BadRequest, e.to_string()))),
+    }
 }
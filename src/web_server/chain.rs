@@ -58,31 +58,52 @@ pub fn chain() -> iron::Chain {
     router.get("/", redirect_to("clusters"), "home");
     router.get("/clusters", pages::clusters_page, "clusters");
     router.get("/cluster/:cluster_id", pages::cluster_page, "cluster");
+    router.get("/cluster/:cluster_id/timeline", pages::cluster_timeline_page, "cluster_timeline");
+    router.get("/cluster/:cluster_id/distribution", pages::distribution_page, "cluster_distribution");
     router.get("/cluster/:cluster_id/topic/:topic_name", pages::topic_page, "topic");
+    router.get("/cluster/:cluster_id/topic/:topic_name/timeline", pages::topic_timeline_page, "topic_timeline");
+    router.get("/cluster/:cluster_id/broker/:broker_id", pages::broker_page, "broker");
+    router.get("/brokers", pages::brokers_page, "brokers");
     router.get("/cluster/:cluster_id/group/:group_name", pages::group_page, "group");
 
     // search
     router.get("/topics", pages::topic_search, "topics");
     router.get("/consumers", pages::consumer_search, "consumers");
 
+    // internal
+    router.get("/internal/cache", pages::cache_page, "internal_cache");
+
     // Various
     router.get("/public/*", AssetsHandler::new("/public/", "resources/web_server/public/"), "public_assets");
     router.get("/meta/request_time/:request_id/", request_timing, "request_timing");
 
     // API
+    router.get("/api/brokers", api::all_brokers, "api_brokers");
     router.get("/api/cluster/:cluster_id/brokers", api::cluster_brokers, "api_cluster_brokers");
+    router.get("/api/cluster/:cluster_id/health", api::cluster_health, "api_cluster_health");
+    router.get("/api/cluster/:cluster_id/events", api::cluster_events_list, "api_cluster_events");
+    router.get("/api/cluster/:cluster_id/distribution", api::cluster_distribution, "api_cluster_distribution");
+    router.get("/api/cluster/:cluster_id/distribution/brokers", api::distribution_brokers, "api_distribution_brokers");
+    router.get("/api/cluster/:cluster_id/distribution/topics", api::distribution_topics, "api_distribution_topics");
+    router.get("/api/cluster/:cluster_id/distribution/matrix", api::distribution_matrix, "api_distribution_matrix");
+    router.get("/api/cluster/:cluster_id/racks", api::cluster_rack_violations, "api_cluster_racks");
+    router.get("/api/cluster/:cluster_id/broker/:broker_id/partitions", api::broker_partitions_list, "api_broker_partitions");
+    router.get("/api/cluster/:cluster_id/broker/:broker_id/topics", api::broker_topics, "api_broker_topics");
+    router.get("/api/cluster/:cluster_id/broker/:broker_id/config", api::broker_config, "api_broker_config");
     router.get("/api/cluster/:cluster_id/topics", api::cluster_topics, "api_cluster_topics");
     router.get("/api/cluster/:cluster_id/groups", api::cluster_groups, "api_cluster_groups");
     router.get("/api/cluster/:cluster_id/topic/:topic_name/topology", api::topic_topology, "api_topic_topology");
     router.get("/api/cluster/:cluster_id/topic/:topic_name/groups", api::topic_groups, "api_topic_groups");
+    router.get("/api/cluster/:cluster_id/topic/:topic_name/config", api::topic_config, "api_topic_config");
+    router.get("/api/cluster/:cluster_id/topic/:topic_name/events", api::topic_events_list, "api_topic_events");
+    router.get("/api/cluster/:cluster_id/topic/:topic_name/racks", api::topic_rack_violations, "api_topic_racks");
     router.get("/api/cluster/:cluster_id/group/:group_name/members", api::group_members, "api_group_members");
     router.get("/api/cluster/:cluster_id/group/:group_name/offsets", api::group_offsets, "api_group_offsets");
     router.get("/api/search/topic", api::topic_search, "api_topic_search");
     router.get("/api/search/consumer", api::consumer_search, "api_consumer_search");
-
-    // todo
-    router.get("/brokers", pages::todo, "brokers");
-    router.get("/cluster/:cluster_id/broker/:broker_id", pages::todo, "broker");
+    router.get("/api/internal/cache/writes", api::cache_writes, "api_internal_cache_writes");
+    router.get("/api/internal/cache", api::cache_status, "api_internal_cache");
+    router.get("/api/internal/cache/lookup/:map_name", api::cache_lookup, "api_internal_cache_lookup");
     iron::Chain::new(router)
 }
 
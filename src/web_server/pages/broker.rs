@@ -0,0 +1,119 @@
+use iron::prelude::{Request, Response};
+use iron::{IronResult, status};
+use maud::PreEscaped;
+use router::Router;
+
+use web_server::pages;
+use web_server::server::CacheType;
+use web_server::view::layout;
+use cache::Selection;
+use metadata::{BrokerId, ClusterId, broker_summaries};
+
+
+fn broker_partitions_table(cluster_id: &ClusterId, broker_id: BrokerId) -> PreEscaped<String> {
+    let api_url = format!("/api/cluster/{}/broker/{}/partitions", cluster_id, broker_id);
+    layout::datatable_ajax("broker-partitions-ajax", &api_url, cluster_id.name(),
+        html! { tr { th "Topic name" th "Partition" th "Role" th "In sync" } }
+    )
+}
+
+fn broker_topics_table(cluster_id: &ClusterId, broker_id: BrokerId) -> PreEscaped<String> {
+    let api_url = format!("/api/cluster/{}/broker/{}/topics", cluster_id, broker_id);
+    layout::datatable_ajax("broker-topics-ajax", &api_url, cluster_id.name(),
+        html! { tr { th "Topic name"
+             th data-toggle="tooltip" data-container="body" title="Average over the last 15 minutes" "Byte rate"
+             th data-toggle="tooltip" data-container="body" title="Average over the last 15 minutes" "Msg rate"
+        }}
+    )
+}
+
+fn broker_config_table(cluster_id: &ClusterId, broker_id: BrokerId) -> PreEscaped<String> {
+    let api_url = format!("/api/cluster/{}/broker/{}/config", cluster_id, broker_id);
+    layout::datatable_ajax("broker-config-ajax", &api_url, cluster_id.name(),
+        html! { tr { th "Name" th "Value" th "Source" } }
+    )
+}
+
+pub fn broker_page(req: &mut Request) -> IronResult<Response> {
+    let cache = req.extensions.get::<CacheType>().unwrap();
+    let cluster_id: ClusterId = req.extensions.get::<Router>().unwrap().find("cluster_id").unwrap().into();
+    let broker_id_str = req.extensions.get::<Router>().unwrap().find("broker_id").unwrap();
+
+    let broker = broker_id_str.parse::<BrokerId>().ok()
+        .and_then(|broker_id| {
+            cache.brokers.get(&cluster_id)
+                .and_then(|brokers| brokers.into_iter().find(|broker| broker.id == broker_id))
+        });
+    let broker = match broker {
+        Some(broker) => broker,
+        None => {
+            return pages::warning_page(req,
+                &format!("Broker: {}", broker_id_str),
+                "The specified broker doesn't exist.")
+        }
+    };
+
+    let summary = broker_summaries(&cache.topics, &Selection::Cluster(&cluster_id))
+        .remove(&(cluster_id.clone(), broker.id))
+        .unwrap_or_default();
+    let total_rate = cache.metrics.get(&(cluster_id.clone(), broker.id))
+        .and_then(|b_metrics| b_metrics.topics.get("__TOTAL__").cloned());
+    let has_config = cache.broker_configs.get(&(cluster_id.clone(), broker.id)).is_some();
+
+    let cluster_link = format!("/cluster/{}/", cluster_id.name());
+    let content = html! {
+        h3 style="margin-top: 0px" "Information"
+        dl class="dl-horizontal" {
+            dt "Cluster name: " dd a href=(cluster_link) (cluster_id)
+            dt "Broker id: " dd (broker.id)
+            dt "Hostname: " dd (broker.hostname)
+            dt "Port: " dd (broker.port)
+            dt "Rack: " dd (broker.rack.clone().unwrap_or_else(|| "Not configured".to_owned()))
+            dt "Kafka version: " dd (broker.release().map(|r| format!("{} or later", r)).unwrap_or_else(|| "Unknown".to_owned()))
+            @if broker.is_controller {
+                dt "Role: " dd "Controller"
+            }
+            dt "Leader for: " dd { (summary.led) " partitions" }
+            dt "Follower for: " dd { (summary.followed) " partitions" }
+            dt "Out of ISR for: " dd { (summary.out_of_isr) " partitions" }
+            @if let Some(rate) = total_rate {
+                dt "Traffic last 15 minutes"
+                dd (format!("{:.1} KB/s {:.0} msg/s", rate.0 / 1000f64, rate.1))
+            } @else {
+                dt "Traffic data" dd "Not available"
+            }
+        }
+        h3 "Partitions"
+        (broker_partitions_table(&cluster_id, broker.id))
+        h3 "Topic traffic"
+        @if total_rate.is_some() {
+            (broker_topics_table(&cluster_id, broker.id))
+        } @else {
+            p "Not available"
+        }
+        h3 "Configuration"
+        @if has_config {
+            (broker_config_table(&cluster_id, broker.id))
+        } @else {
+            p "Not available"
+        }
+    };
+    let html = layout::page(req, &format!("Broker: {} ({})", broker.id, cluster_id), content);
+
+    Ok(Response::with((status::Ok, html)))
+}
+
+pub fn brokers_page(req: &mut Request) -> IronResult<Response> {
+    let table = layout::datatable_ajax("all-brokers-ajax", "/api/brokers", "",
+        html! { tr { th "Cluster" th "Broker id" th "Hostname" th "Port"
+            th "Leader for" th "Follower for" th "Out of ISR"
+            th data-toggle="tooltip" data-container="body"
+                title="Total average over the last 15 minutes" "Total byte rate"
+            th data-toggle="tooltip" data-container="body"
+                title="Total average over the last 15 minutes" "Total msg rate"
+        }}
+    );
+    let html = layout::page(req, "Brokers", table);
+
+    Ok(Response::with((status::Ok, html)))
+}
@@ -0,0 +1,123 @@
+use iron::prelude::{Request, Response};
+use iron::prelude::*;
+use iron::{IronResult, status};
+use maud::PreEscaped;
+use serde_json;
+use urlencoded::UrlEncodedQuery;
+
+use cache::{Cache, MapStats};
+use web_server::server::CacheType;
+use web_server::view::layout;
+
+use std::collections::HashMap;
+
+
+fn format_size(bytes: usize) -> String {
+    if bytes >= 1024 * 1024 {
+        format!("{:.1} MB", bytes as f64 / (1024 * 1024) as f64)
+    } else if bytes >= 1024 {
+        format!("{:.1} KB", bytes as f64 / 1024 as f64)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+fn maps_table(stats: &[MapStats]) -> PreEscaped<String> {
+    html! {
+        table class="table table-striped table-bordered table-hover" {
+            thead {
+                tr {
+                    th "Map" th "Entries" th "Approx. size" th "Memory limit" th "Evicted"
+                    th data-toggle="tooltip" data-container="body" title="Writes per second over the last minute" "Write rate"
+                    th "Last update"
+                    th data-toggle="tooltip" data-container="body" title="Updates received from the cache backend" "Replayed"
+                }
+            }
+            tbody {
+                @for map in stats {
+                    tr {
+                        td (map.name)
+                        td (map.entries)
+                        td (format_size(map.approx_size))
+                        td (map.memory_limit.map(format_size).unwrap_or_else(|| "none".to_owned()))
+                        td (map.evicted)
+                        td (format!("{:.2}/s", map.write_rate))
+                        td (map.last_update.map(|t| t.to_rfc3339()).unwrap_or_else(|| "never".to_owned()))
+                        td (map.replayed)
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn lookup_form(stats: &[MapStats], map_name: &str, key: &str) -> PreEscaped<String> {
+    html! {
+        form class="form-inline" action="/internal/cache" {
+            select class="form-control" name="map" {
+                @for map in stats {
+                    @if map.name == map_name {
+                        option value=(map.name) selected="" (map.name)
+                    } @else {
+                        option value=(map.name) (map.name)
+                    }
+                }
+            }
+            " "
+            input class="form-control" type="text" name="key" style="width: 50%"
+                placeholder="Key as JSON, e.g. [\"cluster\", \"group\", \"topic\"]" value=(key) {}
+            " "
+            button class="btn btn-default" type="submit" "Look up"
+        }
+    }
+}
+
+fn lookup_result(cache: &Cache, map_name: &str, key: &str) -> PreEscaped<String> {
+    match cache.lookup_json(map_name, key) {
+        Ok(Some((value, info))) => {
+            let value = serde_json::to_string_pretty(&value).unwrap_or_else(|e| e.to_string());
+            html! {
+                dl class="dl-horizontal" {
+                    dt "Updated:" dd (info.updated.map(|t| t.to_rfc3339()).unwrap_or_else(|| "unknown".to_owned()))
+                    dt "Source:" dd (info.source.unwrap_or_else(|| "unknown".to_owned()))
+                }
+                pre (value)
+            }
+        },
+        Ok(None) => layout::notification("info", html! { "Key not found" }),
+        Err(e) => layout::notification("warning", html! { (e) }),
+    }
+}
+
+pub fn cache_page(req: &mut Request) -> IronResult<Response> {
+    let params = req.get_ref::<UrlEncodedQuery>().unwrap_or(&HashMap::new()).clone();
+    let cache = req.extensions.get::<CacheType>().unwrap();
+    let stats = cache.map_stats();
+    let writes = cache.write_stats();
+
+    let map_name = params.get("map")
+        .map(|results| results[0].as_str())
+        .unwrap_or("");
+    let key = params.get("key")
+        .map(|results| results[0].as_str())
+        .unwrap_or("");
+
+    let content = html! {
+        h3 style="margin-top: 0px" "Maps"
+        div (maps_table(&stats))
+        h3 "Backend"
+        dl class="dl-horizontal" {
+            dt "Writes:" dd { (writes.written) " written, " (writes.delivered) " delivered, "
+                              (writes.failed) " failed, " (writes.in_flight) " in flight" }
+            dt "Replica lag:" dd (cache.replica_lag().map(|lag| format!("{} messages", lag)).unwrap_or_else(|| "unknown".to_owned()))
+        }
+        h3 "Lookup"
+        (lookup_form(&stats, map_name, key))
+        @if !key.is_empty() {
+            div style="margin-top: 10pt" (lookup_result(cache, map_name, key))
+        }
+    };
+    let html = layout::page(req, "Cache status", content);
+
+    Ok(Response::with((status::Ok, html)))
+}
@@ -6,7 +6,10 @@ use router::Router;
 use web_server::pages;
 use web_server::server::{CacheType, ConfigArc};
 use web_server::view::layout;
-use metadata::{Broker, ClusterId};
+use health::{ClusterHealth, PartitionRef};
+use kafka_api::brokers_by_release;
+use metadata::{Broker, ClusterId, controller_id};
+use rack_awareness::broker_racks;
 
 
 fn broker_table(cluster_id: &ClusterId) -> PreEscaped<String> {
@@ -17,6 +20,9 @@ fn broker_table(cluster_id: &ClusterId) -> PreEscaped<String> {
                 title="Total average over the last 15 minutes" "Total byte rate"
             th data-toggle="tooltip" data-container="body"
                 title="Total average over the last 15 minutes" "Total msg rate"
+            th data-toggle="tooltip" data-container="body"
+                title="Approximated from the supported API versions" "Kafka version"
+            th "Role"
             }
         }
     )
@@ -40,18 +46,93 @@ fn groups_table(cluster_id: &ClusterId) -> PreEscaped<String> {
     )
 }
 
+fn rack_violations_table(cluster_id: &ClusterId) -> PreEscaped<String> {
+    let api_url = format!("/api/cluster/{}/racks", cluster_id);
+    layout::datatable_ajax("rack-violations-ajax", &api_url, cluster_id.name(),
+        html! { tr { th "Topic name" th "Partition" th "Violation" th "Description" } },
+    )
+}
+
+fn partition_list(cluster_id: &ClusterId, partitions: &[PartitionRef]) -> PreEscaped<String> {
+    html! {
+        @for p in partitions {
+            a href=(format!("/cluster/{}/topic/{}", cluster_id, p.topic)) (format!("{}/{}", p.topic, p.partition))
+            " "
+        }
+    }
+}
+
+/// The Kafka versions run by the brokers, flagged if they don't match.
+fn versions_layout(brokers: &[Broker]) -> PreEscaped<String> {
+    let releases = brokers_by_release(brokers);
+    html! {
+        @if releases.is_empty() {
+            "Unknown"
+        } @else {
+            @if releases.len() == 1 {
+                @for release in releases.keys() {
+                    (release) " or later"
+                }
+            } @else {
+                span class="label label-warning" "Mixed versions"
+                @for (release, broker_ids) in &releases {
+                    " " (release) " (brokers "
+                    (broker_ids.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(", ")) ")"
+                }
+            }
+        }
+    }
+}
+
+fn health_section(cluster_id: &ClusterId, health: &ClusterHealth) -> PreEscaped<String> {
+    html! {
+        dl class="dl-horizontal" {
+            dt "Status: " dd (layout::health_badge(health.status))
+            dt "Offline: " dd { (health.offline.len()) " partitions" }
+            dt "Under-replicated: " dd { (health.under_replicated.len()) " partitions" }
+            dt "Below min ISR: " dd { (health.below_min_isr.len()) " partitions" }
+            dt "Missing brokers: " dd {
+                @if health.missing_brokers.is_empty() {
+                    "none"
+                } @else {
+                    @for broker_id in &health.missing_brokers {
+                        (broker_id) " "
+                    }
+                }
+            }
+        }
+        @if !health.offline.is_empty() {
+            h4 "Offline partitions"
+            p (partition_list(cluster_id, &health.offline))
+        }
+        @if !health.below_min_isr.is_empty() {
+            h4 "Partitions below min ISR"
+            p (partition_list(cluster_id, &health.below_min_isr))
+        }
+        @if !health.under_replicated.is_empty() {
+            h4 "Under-replicated partitions"
+            p (partition_list(cluster_id, &health.under_replicated))
+        }
+    }
+}
+
 pub fn cluster_page(req: &mut Request) -> IronResult<Response> {
     let cache = req.extensions.get::<CacheType>().unwrap();
     let ref config = req.extensions.get::<ConfigArc>().unwrap().config;
     let cluster_id = req.extensions.get::<Router>().unwrap().find("cluster_id").unwrap().into();
 
-    if cache.brokers.get(&cluster_id).is_none() {
-        return pages::warning_page(req,
-            &format!("Cluster: {}", cluster_id),
-            "The specified cluster doesn't exist.")
-    }
+    let brokers = match cache.brokers.get(&cluster_id) {
+        Some(brokers) => brokers,
+        None => {
+            return pages::warning_page(req,
+                &format!("Cluster: {}", cluster_id),
+                "The specified cluster doesn't exist.")
+        }
+    };
 
     let cluster_config = config.clusters.get(&cluster_id);
+    let health = ClusterHealth::from_cache(cache, &cluster_id);
+    let racks = broker_racks(&brokers);
     let content = html! {
         h3 style="margin-top: 0px" "Information"
         dl class="dl-horizontal" {
@@ -63,9 +144,30 @@ pub fn cluster_page(req: &mut Request) -> IronResult<Response> {
                 dt "Bootstrap list: " dd "Cluster configuration is missing"
                 dt "Zookeeper: " dd "Cluster configuration is missing"
             }
+            dt "Controller: " dd {
+                @if let Some(controller_id) = controller_id(&brokers) {
+                    a href=(format!("/cluster/{}/broker/{}", cluster_id, controller_id)) { "Broker " (controller_id) }
+                } @else {
+                    "Unknown"
+                }
+            }
+            dt "Kafka version: " dd (versions_layout(&brokers))
+            dt "Timeline: " dd a href=(format!("/cluster/{}/timeline", cluster_id)) "Metadata changes"
+            dt "Distribution: " dd a href=(format!("/cluster/{}/distribution", cluster_id)) "Leaders and replicas by broker"
+        }
+        @if let Some(ref health) = health {
+            h3 "Health"
+            (health_section(&cluster_id, health))
         }
         h3 "Brokers"
         div (broker_table(&cluster_id))
+        h3 "Rack awareness"
+        @if racks.len() < 2 {
+            p "Not configured: the brokers don't span multiple racks"
+        } @else {
+            p { "Racks: " (racks.join(", ")) }
+            (rack_violations_table(&cluster_id))
+        }
         h3 "Topics"
         (topic_table(&cluster_id))
         h3 "Consumer groups"
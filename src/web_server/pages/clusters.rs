@@ -4,11 +4,14 @@ use maud::PreEscaped;
 
 use web_server::server::CacheType;
 use web_server::view::layout;
-use cache::{BrokerCache, TopicCache};
+use cache::{Cache, Selection};
+use health::ClusterHealth;
+use kafka_api::brokers_by_release;
 use metadata::ClusterId;
 
 
-fn cluster_pane_layout(cluster_id: &ClusterId, brokers: usize, topics: usize) -> PreEscaped<String> {
+fn cluster_pane_layout(cluster_id: &ClusterId, brokers: usize, topics: usize, health: Option<ClusterHealth>,
+                       mixed_versions: bool) -> PreEscaped<String> {
     let link = format!("/cluster/{}/", cluster_id.name());
     html! {
         div class="col-lg-4 col-md-6" {
@@ -23,6 +26,18 @@ fn cluster_pane_layout(cluster_id: &ClusterId, brokers: usize, topics: usize) ->
                             }
                             div { (brokers) " brokers" }
                             div { (topics) " topics" }
+                            @if let Some(health) = health {
+                                div title=(format!("{} offline, {} under-replicated, {} below min ISR partitions",
+                                                    health.offline.len(), health.under_replicated.len(),
+                                                    health.below_min_isr.len())) {
+                                    (layout::health_badge(health.status))
+                                }
+                            }
+                            @if mixed_versions {
+                                div title="The brokers run different Kafka versions" {
+                                    span class="label label-warning" "Mixed versions"
+                                }
+                            }
                         }
                     }
                 }
@@ -38,10 +53,12 @@ fn cluster_pane_layout(cluster_id: &ClusterId, brokers: usize, topics: usize) ->
     }
 }
 
-fn cluster_pane(cluster_id: &ClusterId, broker_cache: &BrokerCache, topic_cache: &TopicCache) -> PreEscaped<String> {
-    let broker_count = broker_cache.get(cluster_id).unwrap_or(Vec::new()).len();
-    let topics_count = topic_cache.count(|&(ref c, _)| c == cluster_id);
-    cluster_pane_layout(cluster_id, broker_count, topics_count)
+fn cluster_pane(cluster_id: &ClusterId, cache: &Cache) -> PreEscaped<String> {
+    let brokers = cache.brokers.get(cluster_id).unwrap_or(Vec::new());
+    let topics_count = cache.topics.select_count(&Selection::Cluster(cluster_id));
+    let health = ClusterHealth::from_cache(cache, cluster_id);
+    let mixed_versions = brokers_by_release(&brokers).len() > 1;
+    cluster_pane_layout(cluster_id, brokers.len(), topics_count, health, mixed_versions)
 }
 
 pub fn clusters_page(req: &mut Request) -> IronResult<Response> {
@@ -51,7 +68,7 @@ pub fn clusters_page(req: &mut Request) -> IronResult<Response> {
 
     let content = html! {
         @for cluster_id in &cluster_ids {
-            (cluster_pane(cluster_id, &cache.brokers, &cache.topics))
+            (cluster_pane(cluster_id, cache))
         }
     };
 
@@ -0,0 +1,68 @@
+use iron::prelude::{Request, Response};
+use iron::{IronResult, status};
+use maud::PreEscaped;
+use router::Router;
+
+use web_server::pages;
+use web_server::server::CacheType;
+use web_server::view::layout;
+use distribution::ClusterDistribution;
+use metadata::ClusterId;
+
+
+fn broker_load_table(cluster_id: &ClusterId) -> PreEscaped<String> {
+    let api_url = format!("/api/cluster/{}/distribution/brokers", cluster_id);
+    layout::datatable_ajax("distribution-brokers-ajax", &api_url, cluster_id.name(),
+        html! { tr { th "Broker id" th "Leaders"
+            th data-toggle="tooltip" data-container="body"
+                title="Partitions led by the broker without being their preferred replica" "Non-preferred leaders"
+            th "Replicas"
+        }}
+    )
+}
+
+fn topic_skew_table(cluster_id: &ClusterId) -> PreEscaped<String> {
+    let api_url = format!("/api/cluster/{}/distribution/topics", cluster_id);
+    layout::datatable_ajax("distribution-topics-ajax", &api_url, cluster_id.name(),
+        html! { tr { th "Topic name" th "#Partitions" th "Non-preferred leaders"
+            th data-toggle="tooltip" data-container="body"
+                title="How much the broker with most replicas exceeds an even share: 0 is balanced" "Skew"
+        }}
+    )
+}
+
+pub fn distribution_page(req: &mut Request) -> IronResult<Response> {
+    let cache = req.extensions.get::<CacheType>().unwrap();
+    let cluster_id: ClusterId = req.extensions.get::<Router>().unwrap().find("cluster_id").unwrap().into();
+
+    let distribution = match ClusterDistribution::from_cache(cache, &cluster_id) {
+        Some(distribution) => distribution,
+        None => {
+            return pages::warning_page(req,
+                &format!("Distribution: {}", cluster_id),
+                "The specified cluster doesn't exist.")
+        }
+    };
+
+    let cluster_link = format!("/cluster/{}/", cluster_id.name());
+    let matrix_link = format!("/api/cluster/{}/distribution/matrix", cluster_id);
+    let content = html! {
+        h3 style="margin-top: 0px" "Summary"
+        dl class="dl-horizontal" {
+            dt "Cluster name: " dd a href=(cluster_link) (cluster_id)
+            dt "Partitions: " dd (distribution.partitions)
+            dt "Non-preferred leaders: " dd {
+                (distribution.non_preferred_leaders)
+                (format!(" ({:.1}%)", distribution.non_preferred_leader_percentage))
+            }
+            dt "Broker x topic matrix: " dd a href=(matrix_link) "JSON"
+        }
+        h3 "Brokers"
+        (broker_load_table(&cluster_id))
+        h3 "Topics"
+        (topic_skew_table(&cluster_id))
+    };
+    let html = layout::page(req, &format!("Distribution: {}", cluster_id), content);
+
+    Ok(Response::with((status::Ok, html)))
+}
@@ -19,15 +19,3 @@ pub fn warning_page(req: &Request, title: &str, message: &str) -> IronResult<Res
 pub fn not_found_page(req: &Request) -> IronResult<Response> {
     warning_page(req, "Page not found", "The page you are looking for doesn't exist.")
 }
-
-pub fn todo(req: &mut Request) -> IronResult<Response> {
-    let content = html! {
-        img src="https://media.giphy.com/media/13HBDT4QSTpveU/giphy.gif"
-            style="float:left;max-width:100pt;max-height:100pt"
-        span style="padding: 0.3in; font-size: 16pt" {
-            "It's in my todo list"
-        }
-    };
-    let html = layout::page(req, "This feature is not implemented yet!", content);
-    Ok(Response::with((status::NotFound, html)))
-}
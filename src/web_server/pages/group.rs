@@ -12,7 +12,7 @@ use metadata::{Broker, ClusterId};
 fn group_members_table(cluster_id: &ClusterId, group_name: &str) -> PreEscaped<String> {
     let api_url = format!("/api/cluster/{}/group/{}/members", cluster_id, group_name);
     layout::datatable_ajax("group-members-ajax", &api_url, cluster_id.name(),
-           html! { tr { th "Member id" th "Client id" th "Hostname" } },
+           html! { tr { th "Member id" th "Client id" th "Hostname" th "Assigned partitions" } },
     )
 }
 
@@ -20,7 +20,7 @@ fn group_offsets_table(cluster_id: &ClusterId, group_name: &str) -> PreEscaped<S
     let api_url = format!("/api/cluster/{}/group/{}/offsets", cluster_id, group_name);
     layout::datatable_ajax("group-offsets-ajax", &api_url, cluster_id.name(),
         html! { tr { th "Topic" th "Partition" th "Low mark" th "High mark" th "Current offset"
-                     th "Lag"} },
+                     th "Lag" th "Owner" } },
     )
 }
 
@@ -37,8 +37,9 @@ pub fn group_page(req: &mut Request) -> IronResult<Response> {
 //        return pages::warning_page(req, group_name, "The specified group doesn't exist.")
 //    }
 
-    let group_state = match cache.groups.get(&(cluster_id.to_owned(), group_name.to_owned())) {
-        Some(group) => group.state,
+    let group = cache.groups.get(&(cluster_id.to_owned(), group_name.to_owned()));
+    let group_state = match group {
+        Some(ref group) => group.state.clone(),
         None => "Not registered".to_string(),
     };
 
@@ -48,6 +49,12 @@ pub fn group_page(req: &mut Request) -> IronResult<Response> {
             dt "Cluster name:" dd (cluster_id)
             dt "Group name: " dd (group_name)
             dt "Group state: " dd (group_state)
+            @if let Some(ref group) = group {
+                @if !group.protocol_type.is_empty() {
+                    dt "Protocol type: " dd (group.protocol_type)
+                    dt "Assignment strategy: " dd (group.protocol)
+                }
+            }
         }
         h3 "Members"
         div (group_members_table(&cluster_id, group_name))
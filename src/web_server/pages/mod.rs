@@ -1,15 +1,23 @@
+mod broker;
+mod cache;
 mod cluster;
 mod clusters;
+mod distribution;
 mod error_defaults;
 mod group;
 mod omnisearch;
+mod timeline;
 mod topic;
 
+pub use self::broker::{broker_page, brokers_page};
+pub use self::cache::cache_page;
 pub use self::cluster::cluster_page;
 pub use self::clusters::clusters_page;
-pub use self::error_defaults::{not_found_page, todo};
+pub use self::distribution::distribution_page;
+pub use self::error_defaults::not_found_page;
 pub use self::error_defaults::warning_page;
 pub use self::group::group_page;
+pub use self::timeline::{cluster_timeline_page, topic_timeline_page};
 pub use self::topic::topic_page;
 
 pub use self::omnisearch::{consumer_search, topic_search};
@@ -0,0 +1,59 @@
+use iron::prelude::{Request, Response};
+use iron::{IronResult, status};
+use maud::PreEscaped;
+use router::Router;
+
+use web_server::pages;
+use web_server::server::CacheType;
+use web_server::view::layout;
+use metadata::ClusterId;
+
+
+fn events_table(cluster_id: &ClusterId, api_url: &str) -> PreEscaped<String> {
+    layout::datatable_ajax("events-ajax", api_url, cluster_id.name(),
+        html! { tr { th "Time" th "Event" th "Description" } }
+    )
+}
+
+pub fn cluster_timeline_page(req: &mut Request) -> IronResult<Response> {
+    let cache = req.extensions.get::<CacheType>().unwrap();
+    let cluster_id: ClusterId = req.extensions.get::<Router>().unwrap().find("cluster_id").unwrap().into();
+
+    if cache.brokers.get(&cluster_id).is_none() {
+        return pages::warning_page(req,
+            &format!("Timeline: {}", cluster_id),
+            "The specified cluster doesn't exist.")
+    }
+
+    let api_url = format!("/api/cluster/{}/events", cluster_id);
+    let content = html! {
+        p { "Changes detected in the metadata of " a href=(format!("/cluster/{}/", cluster_id)) (cluster_id) "." }
+        (events_table(&cluster_id, &api_url))
+    };
+    let html = layout::page(req, &format!("Timeline: {}", cluster_id), content);
+
+    Ok(Response::with((status::Ok, html)))
+}
+
+// Deleted topics can still have a timeline, so only the cluster has to exist.
+pub fn topic_timeline_page(req: &mut Request) -> IronResult<Response> {
+    let cache = req.extensions.get::<CacheType>().unwrap();
+    let cluster_id: ClusterId = req.extensions.get::<Router>().unwrap().find("cluster_id").unwrap().into();
+    let topic_name = req.extensions.get::<Router>().unwrap().find("topic_name").unwrap();
+
+    if cache.brokers.get(&cluster_id).is_none() {
+        return pages::warning_page(req,
+            &format!("Timeline: {}", topic_name),
+            "The specified cluster doesn't exist.")
+    }
+
+    let api_url = format!("/api/cluster/{}/topic/{}/events", cluster_id, topic_name);
+    let topic_link = format!("/cluster/{}/topic/{}", cluster_id, topic_name);
+    let content = html! {
+        p { "Changes detected in the metadata of " a href=(topic_link) (topic_name) "." }
+        (events_table(&cluster_id, &api_url))
+    };
+    let html = layout::page(req, &format!("Timeline: {}", topic_name), content);
+
+    Ok(Response::with((status::Ok, html)))
+}
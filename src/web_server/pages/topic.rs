@@ -7,9 +7,26 @@ use web_server::server::CacheType;
 use web_server::view::layout;
 use web_server::pages;
 use metrics::build_topic_metrics;
+use admin::ConfigValue;
 use metadata::ClusterId;
+use rack_awareness::broker_racks;
 
 
+/// The settings most often checked during incidents, shown with the general information.
+const KEY_SETTINGS: &'static [&'static str] = &["cleanup.policy", "retention.ms", "retention.bytes",
+    "min.insync.replicas", "compression.type"];
+
+fn config_value_layout(value: &ConfigValue) -> PreEscaped<String> {
+    let text = value.value.clone().unwrap_or_else(|| "(not set)".to_owned());
+    html! {
+        @if value.is_default {
+            (text) " " small class="text-muted" "(default)"
+        } @else {
+            strong (text) " " small class="text-muted" "(override)"
+        }
+    }
+}
+
 fn topic_table(cluster_id: &ClusterId, topic_name: &str) -> PreEscaped<String> {
     let api_url = format!("/api/cluster/{}/topic/{}/topology", cluster_id, topic_name);
     layout::datatable_ajax("topology-ajax", &api_url, cluster_id.name(),
@@ -17,6 +34,20 @@ fn topic_table(cluster_id: &ClusterId, topic_name: &str) -> PreEscaped<String> {
     )
 }
 
+fn topic_config_table(cluster_id: &ClusterId, topic_name: &str) -> PreEscaped<String> {
+    let api_url = format!("/api/cluster/{}/topic/{}/config", cluster_id, topic_name);
+    layout::datatable_ajax("topic-config-ajax", &api_url, cluster_id.name(),
+        html! { tr { th "Name" th "Value" th "Source" } }
+    )
+}
+
+fn rack_violations_table(cluster_id: &ClusterId, topic_name: &str) -> PreEscaped<String> {
+    let api_url = format!("/api/cluster/{}/topic/{}/racks", cluster_id, topic_name);
+    layout::datatable_ajax("rack-violations-ajax", &api_url, cluster_id.name(),
+        html! { tr { th "Topic name" th "Partition" th "Violation" th "Description" } },
+    )
+}
+
 fn consumer_groups_table(cluster_id: &ClusterId, topic_name: &str) -> PreEscaped<String> {
     let api_url = format!("/api/cluster/{}/topic/{}/groups", cluster_id, topic_name);
     layout::datatable_ajax("groups-ajax", &api_url, cluster_id.name(),
@@ -40,10 +71,19 @@ pub fn topic_page(req: &mut Request) -> IronResult<Response> {
     };
 
     let brokers = cache.brokers.get(&cluster_id).expect("Broker should exist");
+    let racks = broker_racks(&brokers);
 
     // TODO: create function specific for single topic metrics
     let metrics = build_topic_metrics(&cluster_id, &brokers, 100, &cache.metrics)
         .get(topic_name).cloned();
+    let config = cache.topic_configs.get(&(cluster_id.clone(), topic_name.to_owned()));
+    let key_settings = config.as_ref()
+        .map(|config| {
+            KEY_SETTINGS.iter()
+                .filter_map(|name| config.iter().find(|value| value.name == **name))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_else(Vec::new);
     let content = html! {
         h3 style="margin-top: 0px" "General information"
         dl class="dl-horizontal" {
@@ -57,9 +97,25 @@ pub fn topic_page(req: &mut Request) -> IronResult<Response> {
             } @else {
                 dt "Traffic data" dd "Not available"
             }
+            @for value in &key_settings {
+                dt (value.name) dd (config_value_layout(value))
+            }
+            dt "Timeline " dd a href=(format!("/cluster/{}/topic/{}/timeline", cluster_id, topic_name)) "Metadata changes"
         }
         h3 "Topology"
         (topic_table(&cluster_id, topic_name))
+        h3 "Rack awareness"
+        @if racks.len() < 2 {
+            p "Not configured: the brokers don't span multiple racks"
+        } @else {
+            (rack_violations_table(&cluster_id, topic_name))
+        }
+        h3 "Configuration"
+        @if config.is_some() {
+            (topic_config_table(&cluster_id, topic_name))
+        } @else {
+            p "Not available"
+        }
         h3 "Consumer groups"
         (consumer_groups_table(&cluster_id, topic_name))
     };
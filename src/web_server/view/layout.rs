@@ -1,5 +1,6 @@
 use maud::{self, PreEscaped};
 use web_server::server::RequestTimer;
+use health::HealthStatus;
 use iron::Request;
 
 
@@ -56,6 +57,17 @@ pub fn notification(n_type: &str, content: PreEscaped<String>) -> PreEscaped<Str
     }
 }
 
+pub fn health_badge(status: HealthStatus) -> PreEscaped<String> {
+    let (label_class, text) = match status {
+        HealthStatus::Ok => ("label label-success", "Healthy"),
+        HealthStatus::Warning => ("label label-warning", "Degraded"),
+        HealthStatus::Critical => ("label label-danger", "Critical"),
+    };
+    html! {
+        span class=(label_class) (text)
+    }
+}
+
 pub fn datatable_ajax(id: &str, url: &str, param: &str,
                       table_header: PreEscaped<String>) -> PreEscaped<String> {
     let table_id = format!("datatable-{}", id);
@@ -144,6 +156,7 @@ fn navbar_side() -> PreEscaped<String> {
                     // li a href="/" { i class="fa fa-dashboard fa-fw" {}  " Home" }
                     //li a href="/" style="font-size: 12pt" { i class="fa fa-info-circle fa-fw" {}  " Home" }
                     li a href="/clusters/" style="font-size: 12pt" { i class="fa fa-server fa-fw" {}  " Clusters" }
+                    li a href="/brokers/" style="font-size: 12pt" { i class="fa fa-hdd-o fa-fw" {}  " Brokers" }
                     li a href="/topics/" style="font-size: 12pt" { i class="fa fa-exchange fa-fw" {}  " Topics" }
                     li a href="/consumers/" style="font-size: 12pt" { i class="fa fa-cubes fa-fw" {}  " Consumers" }
                     //li {